@@ -4,6 +4,8 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum AppError {
+    #[error("ambiguous airline:")]
+    AmbiguousAirline(String),
     #[error("invalid callsign:")]
     Callsign(String),
     #[error("internal error:")]
@@ -20,4 +22,8 @@ pub enum AppError {
     ParseInt(#[from] ParseIntError),
     #[error("IO error")]
     IoErrir(#[from] io::Error),
+    #[error("csv error")]
+    Csv(#[from] csv::Error),
+    #[error("json error")]
+    Json(#[from] serde_json::Error),
 }
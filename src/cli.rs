@@ -0,0 +1,186 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::input::InputFormat;
+
+/// Update flightroute data in the adsbdb Postgres database, and invalidate the related Redis cache.
+/// Any option given here overrides the same setting from the `.env` file
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Path to the env file, instead of /app_env/.env or ./.env
+    #[arg(long, global = true, value_name = "PATH")]
+    pub env_file: Option<PathBuf>,
+
+    /// Path to a toml config file, instead of CONFIG_FILE, /app_env/adsbdb_updater.toml, or ./adsbdb_updater.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// The `[profile.<name>]` of the config file to use, overriding PROFILE
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// One of error, warn, info, debug, or trace, overriding LOG_DEBUG and LOG_TRACE
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<tracing::Level>,
+
+    /// Defaults to apply
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Apply the input to Postgres, and invalidate the cache
+    Apply(InputArgs),
+    /// Print the changes that the input would make, without writing anything
+    Check(InputArgs),
+    /// Print the current route of each callsign
+    Show {
+        #[arg(required = true)]
+        callsigns: Vec<String>,
+    },
+    /// Write the current route of every callsign in the input, in the input shape
+    Export {
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Convert the input between csv, json, and json lines
+    Convert {
+        #[command(flatten)]
+        input: InputArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Revert every change made by a previous run
+    Rollback {
+        run_id: String,
+        /// Revert flightroutes even if they have been changed again since the run
+        #[arg(long)]
+        force: bool,
+    },
+    /// Inspect the resolved config
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self::Apply(InputArgs::default())
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the resolved config, with any passwords redacted
+    Check,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct InputArgs {
+    /// An input file, or `-` for stdin, can be given multiple times, overriding INPUT_PATH
+    #[arg(short, long = "input", value_name = "PATH")]
+    pub inputs: Vec<String>,
+
+    /// One of csv, json, jsonl, or ndjson, overriding INPUT_FORMAT and the file extension
+    #[arg(long, value_name = "FORMAT", value_parser = parse_format)]
+    pub format: Option<InputFormat>,
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Where to write the output, defaults to stdout
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// One of csv, json, jsonl, or ndjson
+    #[arg(long, value_name = "FORMAT", value_parser = parse_format, default_value = "csv")]
+    pub to: InputFormat,
+}
+
+fn parse_format(value: &str) -> Result<InputFormat, String> {
+    InputFormat::parse(value).ok_or_else(|| format!("'{value}' is not a known format"))
+}
+
+/// Run tests with
+///
+/// cargo watch -q -c -w src/ -x 'test cli_ -- --nocapture'
+#[cfg(test)]
+#[allow(clippy::pedantic, clippy::nursery, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_verify() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn cli_parse() {
+        // ACTION
+        let result = Cli::try_parse_from(["adsbdb_updater"]).unwrap();
+
+        // CHECK
+        assert!(result.command.is_none());
+
+        // ACTION
+        let result = Cli::try_parse_from([
+            "adsbdb_updater",
+            "--log-level",
+            "debug",
+            "apply",
+            "-i",
+            "first.csv",
+            "--input",
+            "second.json",
+            "--format",
+            "jsonl",
+        ])
+        .unwrap();
+
+        // CHECK
+        assert_eq!(result.log_level, Some(tracing::Level::DEBUG));
+        match result.command.unwrap() {
+            Command::Apply(input) => {
+                assert_eq!(input.inputs, ["first.csv", "second.json"]);
+                assert_eq!(input.format, Some(InputFormat::JsonLines));
+            }
+            _ => unreachable!(),
+        }
+
+        // ACTION
+        let result = Cli::try_parse_from([
+            "adsbdb_updater",
+            "rollback",
+            "abc",
+            "--force",
+            "--env-file",
+            "staging.env",
+            "--profile",
+            "staging",
+        ])
+        .unwrap();
+
+        // CHECK
+        assert_eq!(result.env_file, Some(PathBuf::from("staging.env")));
+        assert_eq!(result.profile, Some("staging".to_owned()));
+        match result.command.unwrap() {
+            Command::Rollback { run_id, force } => {
+                assert_eq!(run_id, "abc");
+                assert!(force);
+            }
+            _ => unreachable!(),
+        }
+
+        // ACTION
+        let result = Cli::try_parse_from(["adsbdb_updater", "convert", "--to", "xml"]);
+
+        // CHECK
+        assert!(result.is_err());
+    }
+}
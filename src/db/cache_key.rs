@@ -0,0 +1,211 @@
+use std::{collections::HashSet, fmt};
+
+use super::ModelFlightroute;
+
+/// The adsbdb api caches a flightroute under `callsign::[callsign]`, for whichever form of the callsign was requested.
+/// Aircraft lookups that include a callsign also embed the route, under keys ending in `callsign::[callsign]`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CacheKey {
+    /// A single, exact, key
+    Callsign(String),
+    /// A `SCAN MATCH` pattern, for aircraft-plus-route keys, the `?` stops it from also matching the plain callsign key
+    AircraftCallsign(String),
+}
+
+/// Matches every aircraft-plus-route key, for any callsign, so that all of the patterns in a batch can be expanded with a single `SCAN`
+pub const AIRCRAFT_CALLSIGN_PATTERN: &str = "*?callsign::*";
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Callsign(x) => write!(f, "callsign::{x}"),
+            Self::AircraftCallsign(x) => write!(f, "*?callsign::{x}"),
+        }
+    }
+}
+
+impl CacheKey {
+    /// Every key, and key pattern, that can hold a cached copy of a flightroute.
+    /// Covers the callsign as it was looked up, which for N-Numbers and registrations is the only form, and the IATA and ICAO forms
+    pub fn flightroute(flightroute: &ModelFlightroute) -> Vec<Self> {
+        let mut callsigns = [
+            Some(&flightroute.callsign),
+            flightroute.callsign_iata.as_ref(),
+            flightroute.callsign_icao.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|callsign| !callsign.is_empty())
+        .collect::<Vec<_>>();
+        callsigns.sort();
+        callsigns.dedup();
+
+        callsigns
+            .iter()
+            .map(|callsign| Self::Callsign((*callsign).clone()))
+            .chain(
+                callsigns
+                    .iter()
+                    .map(|callsign| Self::AircraftCallsign((*callsign).clone())),
+            )
+            .collect()
+    }
+
+    /// The callsign of an exact key, which can be re-populated with a fresh flightroute
+    pub fn callsign(&self) -> Option<&str> {
+        match self {
+            Self::Callsign(x) => Some(x),
+            Self::AircraftCallsign(_) => None,
+        }
+    }
+
+    /// A pattern needs to be expanded, with `SCAN`, into the keys that currently match it
+    pub const fn is_pattern(&self) -> bool {
+        matches!(self, Self::AircraftCallsign(_))
+    }
+
+    /// Expand a batch of keys into the exact keys to delete, sorted and deduplicated.
+    /// `scanned` is every key known to match `AIRCRAFT_CALLSIGN_PATTERN`, a scanned key is kept if it matches any of the patterns in the batch
+    pub fn expand<'a>(keys: &[Self], scanned: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let callsigns = keys
+            .iter()
+            .filter_map(|key| match key {
+                Self::AircraftCallsign(x) => Some(x.as_str()),
+                Self::Callsign(_) => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let mut expanded = keys
+            .iter()
+            .filter(|key| !key.is_pattern())
+            .map(ToString::to_string)
+            .chain(
+                scanned
+                    .into_iter()
+                    .filter(|scanned| {
+                        scanned
+                            .rsplit_once("callsign::")
+                            .is_some_and(|(prefix, callsign)| {
+                                !prefix.is_empty() && callsigns.contains(callsign)
+                            })
+                    })
+                    .map(ToOwned::to_owned),
+            )
+            .collect::<Vec<_>>();
+        expanded.sort();
+        expanded.dedup();
+        expanded
+    }
+}
+
+/// Run tests with
+///
+/// cargo watch -q -c -w src/ -x 'test cache_key_ -- --nocapture'
+#[cfg(test)]
+#[allow(clippy::pedantic, clippy::nursery, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_key_display() {
+        // ACTION
+        let result01 = CacheKey::Callsign("SWA2238".to_owned());
+        let result02 = CacheKey::AircraftCallsign("SWA2238".to_owned());
+
+        // CHECK
+        assert_eq!(result01.to_string(), "callsign::SWA2238");
+        assert!(!result01.is_pattern());
+        assert_eq!(result01.callsign(), Some("SWA2238"));
+        assert_eq!(result02.to_string(), "*?callsign::SWA2238");
+        assert!(result02.is_pattern());
+        assert!(result02.callsign().is_none());
+    }
+
+    #[test]
+    fn cache_key_flightroute() {
+        // FIXTURES
+        let other = ModelFlightroute {
+            callsign: "N123AB".to_owned(),
+            ..Default::default()
+        };
+        let iata = ModelFlightroute {
+            callsign: "WN2238".to_owned(),
+            callsign_iata: Some("WN2238".to_owned()),
+            callsign_icao: Some("SWA2238".to_owned()),
+            ..Default::default()
+        };
+        let icao = ModelFlightroute {
+            callsign: "SWA2238".to_owned(),
+            callsign_iata: Some(String::new()),
+            callsign_icao: Some("SWA2238".to_owned()),
+            ..Default::default()
+        };
+
+        // ACTION
+        let result01 = CacheKey::flightroute(&other);
+        let result02 = CacheKey::flightroute(&iata);
+        let result03 = CacheKey::flightroute(&icao);
+
+        // CHECK
+        assert_eq!(
+            result01,
+            [
+                CacheKey::Callsign("N123AB".to_owned()),
+                CacheKey::AircraftCallsign("N123AB".to_owned()),
+            ]
+        );
+        assert_eq!(
+            result02,
+            [
+                CacheKey::Callsign("SWA2238".to_owned()),
+                CacheKey::Callsign("WN2238".to_owned()),
+                CacheKey::AircraftCallsign("SWA2238".to_owned()),
+                CacheKey::AircraftCallsign("WN2238".to_owned()),
+            ]
+        );
+        assert_eq!(
+            result03,
+            [
+                CacheKey::Callsign("SWA2238".to_owned()),
+                CacheKey::AircraftCallsign("SWA2238".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cache_key_expand() {
+        // FIXTURES
+        let keys = [
+            CacheKey::Callsign("SWA2238".to_owned()),
+            CacheKey::AircraftCallsign("SWA2238".to_owned()),
+            CacheKey::Callsign("N123AB".to_owned()),
+            CacheKey::AircraftCallsign("N123AB".to_owned()),
+            CacheKey::Callsign("SWA2238".to_owned()),
+        ];
+        let scanned = [
+            "aircraft::A1B2C3::callsign::SWA2238",
+            "aircraft::N123AB::callsign::N123AB",
+            "aircraft::A1B2C3::callsign::SWA22380",
+            "aircraft::A1B2C3::callsign::WN2238",
+            "callsign::SWA2238",
+        ];
+
+        // ACTION
+        let result01 = CacheKey::expand(&keys, scanned);
+        let result02 = CacheKey::expand(&keys[..1], scanned);
+        let result03 = CacheKey::expand(&[], []);
+
+        // CHECK
+        assert_eq!(
+            result01,
+            [
+                "aircraft::A1B2C3::callsign::SWA2238",
+                "aircraft::N123AB::callsign::N123AB",
+                "callsign::N123AB",
+                "callsign::SWA2238",
+            ]
+        );
+        assert_eq!(result02, ["callsign::SWA2238"]);
+        assert!(result03.is_empty());
+    }
+}
@@ -1,21 +1,62 @@
-use crate::{app_error::AppError, parse_env::AppEnv};
-use redis::{aio::Connection, ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
-use sqlx::{postgres::PgPoolOptions, ConnectOptions, PgPool};
+use crate::{
+    app_error::AppError,
+    callsign::{Callsign, Validate},
+    parse_env::{AppEnv, PgConfig, RedisAddr},
+};
+use redis::{
+    aio::Connection, ConnectionAddr, ConnectionInfo, IntoConnectionInfo, RedisConnectionInfo,
+};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    ConnectOptions, PgPool,
+};
+use std::collections::HashSet;
+use std::str::FromStr;
 use std::time::Duration;
 
+/// The ttl, in seconds, that the adsbdb api caches a flightroute for
+const ONE_WEEK: usize = 60 * 60 * 24 * 7;
+
+/// The hash field that the adsbdb api stores each cached value under
+const HASH_FIELD: &str = "data";
+
+/// The `COUNT` hint for each `SCAN`, a larger count means fewer round trips over the whole keyspace
+const SCAN_COUNT: usize = 1000;
+
+mod cache_key;
 mod model_airport;
+mod model_audit;
 mod model_flightroute;
 
-pub use model_airport::ModelAirport;
-pub use model_flightroute::ModelFlightroute;
+pub use cache_key::CacheKey;
+use cache_key::AIRCRAFT_CALLSIGN_PATTERN;
+pub use model_airport::{AirportCode, ModelAirport};
+pub use model_audit::{AuditEntry, ModelAudit};
+pub use model_flightroute::{Midpoint, ModelFlightroute, RouteIds};
 
+/// Get a Postgres pool, from either a full url, or separate fields, with an optional sslmode, and custom ca certificate, for tls connections
 pub async fn db_pool(app_env: &AppEnv) -> Result<PgPool, AppError> {
-    let mut options = sqlx::postgres::PgConnectOptions::new()
-        .host(&app_env.pg_host)
-        .port(app_env.pg_port)
-        .database(&app_env.pg_database)
-        .username(&app_env.pg_user)
-        .password(&app_env.pg_pass);
+    let mut options = match &app_env.pg {
+        PgConfig::Url(url) => PgConnectOptions::from_str(url.expose())?,
+        PgConfig::Fields {
+            database,
+            host,
+            pass,
+            port,
+            user,
+        } => PgConnectOptions::new()
+            .host(host)
+            .port(*port)
+            .database(database)
+            .username(user)
+            .password(pass.expose()),
+    };
+    if let Some(ssl_mode) = app_env.pg_ssl_mode {
+        options = options.ssl_mode(ssl_mode);
+    }
+    if let Some(ssl_root_cert) = app_env.pg_ssl_root_cert.as_ref() {
+        options = options.ssl_root_cert(ssl_root_cert);
+    }
 
     let acquire_timeout = Duration::from_secs(5);
     let idle_timeout = Duration::from_secs(30);
@@ -32,19 +73,228 @@ pub async fn db_pool(app_env: &AppEnv) -> Result<PgPool, AppError> {
         .await?)
 }
 
-/// Get an async redis connection
-pub async fn get_connection(app_env: &AppEnv) -> Result<Connection, AppError> {
-    let connection_info = ConnectionInfo {
-        redis: RedisConnectionInfo {
-            db: i64::from(app_env.redis_database),
-            password: Some(app_env.redis_password.clone()),
-            username: None,
+/// A Redis connection, along with the aircraft-plus-route keys found by a single `SCAN`, the first time a pattern needs to be expanded.
+/// The scan is reused for the rest of the run, rather than scanning the whole keyspace for every batch, so keys cached after it was taken aren't deleted
+pub struct Redis {
+    connection: Connection,
+    aircraft_keys: Option<HashSet<String>>,
+}
+
+/// Get an async redis connection, over tcp, with or without tls, a unix socket, or from a full url
+pub async fn get_connection(app_env: &AppEnv) -> Result<Redis, AppError> {
+    let redis = RedisConnectionInfo {
+        db: i64::from(app_env.redis_database),
+        password: app_env
+            .redis_password
+            .as_ref()
+            .map(|i| i.expose().to_owned()),
+        username: app_env.redis_username.clone(),
+    };
+    let connection_info = match &app_env.redis_addr {
+        RedisAddr::Url(url) => url.expose().into_connection_info()?,
+        RedisAddr::Unix(path) => ConnectionInfo {
+            redis,
+            addr: ConnectionAddr::Unix(path.into()),
+        },
+        RedisAddr::Tcp {
+            host,
+            port,
+            tls: false,
+        } => ConnectionInfo {
+            redis,
+            addr: ConnectionAddr::Tcp(host.clone(), *port),
+        },
+        RedisAddr::Tcp {
+            host,
+            port,
+            tls: true,
+        } => ConnectionInfo {
+            redis,
+            addr: ConnectionAddr::TcpTls {
+                host: host.clone(),
+                port: *port,
+                insecure: false,
+                tls_params: None,
+            },
         },
-        addr: ConnectionAddr::Tcp(app_env.redis_host.clone(), app_env.redis_port),
     };
     let client = redis::Client::open(connection_info)?;
     match tokio::time::timeout(Duration::from_secs(10), client.get_async_connection()).await {
-        Ok(con) => Ok(con?),
+        Ok(con) => Ok(Redis {
+            connection: con?,
+            aircraft_keys: None,
+        }),
         Err(_) => Err(AppError::Internal("Unable to connect to redis".to_owned())),
     }
 }
+
+/// Every key that currently matches `AIRCRAFT_CALLSIGN_PATTERN`, with a single `SCAN` of the keyspace
+async fn scan_aircraft_keys(redis: &mut Connection) -> Result<HashSet<String>, AppError> {
+    let mut cmd = redis::cmd("SCAN");
+    cmd.cursor_arg(0)
+        .arg("MATCH")
+        .arg(AIRCRAFT_CALLSIGN_PATTERN)
+        .arg("COUNT")
+        .arg(SCAN_COUNT);
+    let mut output = HashSet::new();
+    let mut iter = cmd.iter_async::<String>(redis).await?;
+    while let Some(key) = iter.next_item().await {
+        output.insert(key);
+    }
+    Ok(output)
+}
+
+/// A pipeline of multi-key `UNLINK`s, of at most `batch_size` keys each
+fn unlink_pipeline(keys: &[String], batch_size: usize) -> redis::Pipeline {
+    let mut pipe = redis::pipe();
+    for batch in keys.chunks(batch_size.max(1)) {
+        pipe.cmd("UNLINK").arg(batch);
+    }
+    pipe
+}
+
+/// Delete cache keys, should only be called once the related changes have been committed to Postgres.
+/// Any patterns are expanded against the aircraft keys scanned once per run, and then every key is removed with multi-key `UNLINK`s, of at most `batch_size` keys each, sent as a single pipeline.
+/// Returns the number of keys that actually existed
+pub async fn delete_keys(
+    redis: &mut Redis,
+    keys: &[CacheKey],
+    batch_size: usize,
+) -> Result<usize, AppError> {
+    if redis.aircraft_keys.is_none() && keys.iter().any(CacheKey::is_pattern) {
+        redis.aircraft_keys = Some(scan_aircraft_keys(&mut redis.connection).await?);
+    }
+    let expanded = CacheKey::expand(
+        keys,
+        redis.aircraft_keys.iter().flatten().map(String::as_str),
+    );
+    if expanded.is_empty() {
+        return Ok(0);
+    }
+    let deleted = unlink_pipeline(&expanded, batch_size)
+        .query_async::<_, Vec<usize>>(&mut redis.connection)
+        .await?;
+    if let Some(aircraft_keys) = redis.aircraft_keys.as_mut() {
+        for key in &expanded {
+            aircraft_keys.remove(key);
+        }
+    }
+    Ok(deleted.into_iter().sum())
+}
+
+/// Add the commands to cache a flightroute, exactly as the adsbdb api's `insert_cache` does, an `HSET` of the serialized `Option<ModelFlightroute>` under the `data` field, and an `EXPIRE`
+fn insert_cache(
+    pipe: &mut redis::Pipeline,
+    key: &str,
+    flightroute: Option<&ModelFlightroute>,
+) -> Result<(), AppError> {
+    pipe.hset(key, HASH_FIELD, serde_json::to_string(&flightroute)?)
+        .ignore()
+        .expire(key, ONE_WEEK)
+        .ignore();
+    Ok(())
+}
+
+/// Re-fetch the flightroute for every exact callsign key, and cache it, in the same hash format, and with the same ttl, as the adsbdb api.
+/// Should only be called once the keys have been deleted, a callsign which no longer has a flightroute is left uncached.
+/// Returns the number of keys set
+pub async fn warm_keys(
+    postgres: &PgPool,
+    redis: &mut Redis,
+    keys: &[CacheKey],
+) -> Result<usize, AppError> {
+    let mut keys = keys.iter().collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    let mut pipe = redis::pipe();
+    let mut count = 0;
+    for key in keys {
+        let Some(callsign) = key.callsign().and_then(|i| Callsign::validate(i).ok()) else {
+            continue;
+        };
+        if let Some(flightroute) = ModelFlightroute::get(postgres, &callsign).await? {
+            insert_cache(&mut pipe, &key.to_string(), Some(&flightroute))?;
+            count += 1;
+        }
+    }
+    if count > 0 {
+        pipe.query_async::<_, ()>(&mut redis.connection).await?;
+    }
+    Ok(count)
+}
+
+/// Run tests with
+///
+/// cargo watch -q -c -w src/ -x 'test db_ -- --nocapture'
+#[cfg(test)]
+#[allow(clippy::pedantic, clippy::nursery, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn db_insert_cache() {
+        // FIXTURES
+        let flightroute = ModelFlightroute {
+            callsign: "SWA2238".to_owned(),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&flightroute).unwrap();
+        let mut hset = redis::cmd("HSET");
+        hset.arg("callsign::SWA2238").arg("data").arg(&json);
+        let mut expire = redis::cmd("EXPIRE");
+        expire.arg("callsign::SWA2238").arg(604_800);
+
+        // ACTION
+        let mut pipe = redis::pipe();
+        insert_cache(&mut pipe, "callsign::SWA2238", Some(&flightroute)).unwrap();
+
+        // CHECK
+        assert_eq!(
+            pipe.get_packed_pipeline(),
+            [hset.get_packed_command(), expire.get_packed_command()].concat()
+        );
+        assert_eq!(serde_json::to_string(&Some(&flightroute)).unwrap(), json);
+        assert!(json.starts_with(r#"{"callsign":"SWA2238","#));
+    }
+
+    #[test]
+    fn db_unlink_pipeline() {
+        // FIXTURES
+        let keys = (0..5)
+            .map(|i| format!("callsign::SWA{i}"))
+            .collect::<Vec<_>>();
+        let unlink = |keys: &[&str]| {
+            let mut cmd = redis::cmd("UNLINK");
+            cmd.arg(keys);
+            cmd.get_packed_command()
+        };
+
+        // ACTION
+        let result01 = unlink_pipeline(&keys, 2).get_packed_pipeline();
+        let result02 = unlink_pipeline(&keys, 0).get_packed_pipeline();
+        let result03 = unlink_pipeline(&keys, 500).get_packed_pipeline();
+
+        // CHECK
+        assert_eq!(
+            result01,
+            [
+                unlink(&["callsign::SWA0", "callsign::SWA1"]),
+                unlink(&["callsign::SWA2", "callsign::SWA3"]),
+                unlink(&["callsign::SWA4"]),
+            ]
+            .concat()
+        );
+        assert_eq!(
+            result02,
+            keys.iter()
+                .map(|key| unlink(&[key.as_str()]))
+                .collect::<Vec<_>>()
+                .concat()
+        );
+        assert_eq!(
+            result03,
+            unlink(&keys.iter().map(String::as_str).collect::<Vec<_>>())
+        );
+    }
+}
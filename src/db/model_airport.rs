@@ -1,25 +1,174 @@
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::PgExecutor;
+use std::fmt;
 
 use crate::app_error::AppError;
 
-#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// An airport code, either a 4 character ICAO code, or a 3 character IATA code.
+/// A `icao:` or `iata:` prefix forces the type, so codes of any other length can still be used
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AirportCode {
+    Icao(String),
+    Iata(String),
+}
+
+impl fmt::Display for AirportCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Icao(x) | Self::Iata(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+impl AirportCode {
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        let valid = |code: &str| {
+            !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        };
+        if let Some((prefix, code)) = input.split_once(':') {
+            let code = code.trim().to_uppercase();
+            if !valid(&code) {
+                return None;
+            }
+            return match prefix.trim().to_lowercase().as_str() {
+                "icao" => Some(Self::Icao(code)),
+                "iata" => Some(Self::Iata(code)),
+                _ => None,
+            };
+        }
+        let code = input.to_uppercase();
+        if !valid(&code) {
+            return None;
+        }
+        match code.len() {
+            3 => Some(Self::Iata(code)),
+            4 => Some(Self::Icao(code)),
+            _ => None,
+        }
+    }
+
+    /// The type of code, either `icao` or `iata`
+    pub const fn code_type(&self) -> &'static str {
+        match self {
+            Self::Icao(_) => "icao",
+            Self::Iata(_) => "iata",
+        }
+    }
+
+    /// The code as it would be written in an input file, only prefixed when the length alone wouldn't give the same type
+    pub fn to_input(&self) -> String {
+        match Self::parse(&self.to_string()) {
+            Some(parsed) if &parsed == self => self.to_string(),
+            _ => format!("{}:{self}", self.code_type()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ModelAirport {
     pub airport_id: i64,
+    /// The code that this airport was matched by
+    pub code: AirportCode,
 }
 
 impl ModelAirport {
-    pub async fn get(db: &PgPool, airport_icao: &str) -> Result<Option<Self>, AppError> {
-        let query = r#"
+    pub async fn get(
+        db: impl PgExecutor<'_>,
+        code: &AirportCode,
+    ) -> Result<Option<Self>, AppError> {
+        let query = match code {
+            AirportCode::Icao(_) => {
+                r"
+SELECT
+    airport_id
+FROM airport
+LEFT JOIN airport_icao_code ai USING(airport_icao_code_id)
+WHERE
+    ai.icao_code = $1"
+            }
+            AirportCode::Iata(_) => {
+                r"
 SELECT
     airport_id
 FROM airport
 LEFT JOIN airport_iata_code ai USING(airport_iata_code_id)
 WHERE
-    ai.iata_code = $1"#;
-        Ok(sqlx::query_as::<_, Self>(query)
-            .bind(airport_icao)
+    ai.iata_code = $1"
+            }
+        };
+        Ok(sqlx::query_scalar::<_, i64>(query)
+            .bind(code.to_string())
             .fetch_optional(db)
-            .await?)
+            .await?
+            .map(|airport_id| Self {
+                airport_id,
+                code: code.clone(),
+            }))
+    }
+}
+
+/// Run tests with
+///
+/// cargo watch -q -c -w src/ -x 'test model_airport_ -- --nocapture'
+#[cfg(test)]
+#[allow(clippy::pedantic, clippy::nursery, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_airport_code_parse() {
+        // ACTION
+        let result01 = AirportCode::parse("pdx");
+        let result02 = AirportCode::parse("KPDX");
+        let result03 = AirportCode::parse("icao:00ak");
+        let result04 = AirportCode::parse("IATA: PDX");
+        let result05 = AirportCode::parse("icao:KPDX");
+
+        // CHECK
+        assert_eq!(result01, Some(AirportCode::Iata("PDX".to_owned())));
+        assert_eq!(result02, Some(AirportCode::Icao("KPDX".to_owned())));
+        assert_eq!(result03, Some(AirportCode::Icao("00AK".to_owned())));
+        assert_eq!(result04, Some(AirportCode::Iata("PDX".to_owned())));
+        assert_eq!(result05, Some(AirportCode::Icao("KPDX".to_owned())));
+        assert_eq!(result05.unwrap().code_type(), "icao");
+    }
+
+    #[test]
+    fn model_airport_code_to_input() {
+        // ACTION
+        let result01 = AirportCode::Icao("KPDX".to_owned()).to_input();
+        let result02 = AirportCode::Iata("PDX".to_owned()).to_input();
+        let result03 = AirportCode::Icao("CYB".to_owned()).to_input();
+        let result04 = AirportCode::Icao("00AKA".to_owned()).to_input();
+
+        // CHECK
+        assert_eq!(result01, "KPDX");
+        assert_eq!(result02, "PDX");
+        assert_eq!(result03, "icao:CYB");
+        assert_eq!(result04, "icao:00AKA");
+        assert_eq!(
+            AirportCode::parse(&result03),
+            Some(AirportCode::Icao("CYB".to_owned()))
+        );
+    }
+
+    #[test]
+    fn model_airport_code_parse_invalid() {
+        // ACTION
+        let result01 = AirportCode::parse("");
+        let result02 = AirportCode::parse("PD");
+        let result03 = AirportCode::parse("KPDXA");
+        let result04 = AirportCode::parse("faa:PDX");
+        let result05 = AirportCode::parse("icao:");
+        let result06 = AirportCode::parse("P X");
+
+        // CHECK
+        assert!(result01.is_none());
+        assert!(result02.is_none());
+        assert!(result03.is_none());
+        assert!(result04.is_none());
+        assert!(result05.is_none());
+        assert!(result06.is_none());
     }
 }
@@ -0,0 +1,197 @@
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+
+use crate::app_error::AppError;
+
+use super::RouteIds;
+
+/// A single change to a flightroute, to be written in the same transaction as the change itself
+#[derive(Debug, Clone)]
+pub struct AuditEntry<'a> {
+    pub run_id: &'a str,
+    pub source: &'a str,
+    pub flightroute_id: i64,
+    pub flightroute_callsign_id: Option<i64>,
+    pub callsign: String,
+    pub action: &'static str,
+    pub old: RouteIds,
+    pub new: RouteIds,
+}
+
+/// A recorded change to a flightroute
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct ModelAudit {
+    pub flightroute_update_audit_id: i64,
+    pub run_id: String,
+    pub flightroute_id: i64,
+    pub flightroute_callsign_id: Option<i64>,
+    pub callsign: String,
+    pub action: String,
+    pub old_airport_origin_id: Option<i64>,
+    pub old_airport_midpoint_id: Option<i64>,
+    pub old_airport_destination_id: Option<i64>,
+    pub new_airport_origin_id: Option<i64>,
+    pub new_airport_midpoint_id: Option<i64>,
+    pub new_airport_destination_id: Option<i64>,
+    pub source: String,
+}
+
+impl ModelAudit {
+    /// Create the audit table, and its index, if they don't already exist
+    pub async fn create_table(db: &PgPool) -> Result<(), AppError> {
+        let query = r"
+CREATE TABLE IF NOT EXISTS flightroute_update_audit (
+    flightroute_update_audit_id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    run_id TEXT NOT NULL,
+    flightroute_id BIGINT NOT NULL,
+    flightroute_callsign_id BIGINT,
+    callsign TEXT NOT NULL,
+    action TEXT NOT NULL,
+    old_airport_origin_id BIGINT,
+    old_airport_midpoint_id BIGINT,
+    old_airport_destination_id BIGINT,
+    new_airport_origin_id BIGINT,
+    new_airport_midpoint_id BIGINT,
+    new_airport_destination_id BIGINT,
+    source TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS flightroute_update_audit_run_id_idx ON flightroute_update_audit(run_id);";
+        db.execute(query).await?;
+        Ok(())
+    }
+
+    /// Generate a new, unique, run id
+    pub async fn new_run_id(db: &PgPool) -> Result<String, AppError> {
+        Ok(
+            sqlx::query_scalar::<_, String>("SELECT gen_random_uuid()::TEXT")
+                .fetch_one(db)
+                .await?,
+        )
+    }
+
+    pub async fn insert(
+        transaction: &mut Transaction<'_, Postgres>,
+        entry: &AuditEntry<'_>,
+    ) -> Result<(), AppError> {
+        let query = r"
+INSERT INTO flightroute_update_audit(
+    run_id, flightroute_id, flightroute_callsign_id, callsign, action,
+    old_airport_origin_id, old_airport_midpoint_id, old_airport_destination_id,
+    new_airport_origin_id, new_airport_midpoint_id, new_airport_destination_id,
+    source
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)";
+        sqlx::query(query)
+            .bind(entry.run_id)
+            .bind(entry.flightroute_id)
+            .bind(entry.flightroute_callsign_id)
+            .bind(&entry.callsign)
+            .bind(entry.action)
+            .bind(entry.old.origin)
+            .bind(entry.old.midpoint)
+            .bind(entry.old.destination)
+            .bind(entry.new.origin)
+            .bind(entry.new.midpoint)
+            .bind(entry.new.destination)
+            .bind(entry.source)
+            .execute(&mut *transaction)
+            .await?;
+        Ok(())
+    }
+
+    /// Get every change made by a run, newest first, so that they can be reverted in order
+    pub async fn get_run(db: &PgPool, run_id: &str) -> Result<Vec<Self>, AppError> {
+        let query = r"
+SELECT
+    flightroute_update_audit_id, run_id, flightroute_id, flightroute_callsign_id, callsign, action,
+    old_airport_origin_id, old_airport_midpoint_id, old_airport_destination_id,
+    new_airport_origin_id, new_airport_midpoint_id, new_airport_destination_id,
+    source
+FROM flightroute_update_audit
+WHERE run_id = $1
+ORDER BY flightroute_update_audit_id DESC";
+        Ok(sqlx::query_as::<_, Self>(query)
+            .bind(run_id)
+            .fetch_all(db)
+            .await?)
+    }
+
+    /// The airport ids before the change
+    pub const fn old_route_ids(&self) -> RouteIds {
+        RouteIds {
+            origin: self.old_airport_origin_id,
+            midpoint: self.old_airport_midpoint_id,
+            destination: self.old_airport_destination_id,
+        }
+    }
+
+    /// The airport ids after the change
+    pub const fn new_route_ids(&self) -> RouteIds {
+        RouteIds {
+            origin: self.new_airport_origin_id,
+            midpoint: self.new_airport_midpoint_id,
+            destination: self.new_airport_destination_id,
+        }
+    }
+
+    /// The current airport ids of the flightroute, `None` if it no longer exists
+    pub async fn current(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<Option<RouteIds>, AppError> {
+        let query = "SELECT airport_origin_id, airport_midpoint_id, airport_destination_id FROM flightroute WHERE flightroute_id = $1 FOR UPDATE";
+        Ok(
+            sqlx::query_as::<_, (Option<i64>, Option<i64>, Option<i64>)>(query)
+                .bind(self.flightroute_id)
+                .fetch_optional(&mut *transaction)
+                .await?
+                .map(|(origin, midpoint, destination)| RouteIds {
+                    origin,
+                    midpoint,
+                    destination,
+                }),
+        )
+    }
+
+    /// Put the flightroute back to how it was before this change.
+    /// A created flightroute is deleted, a deleted flightroute is re-inserted with its original id, anything else is updated
+    pub async fn revert(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        current: Option<RouteIds>,
+    ) -> Result<(), AppError> {
+        let old = self.old_route_ids();
+        if old == RouteIds::default() {
+            let query = "DELETE FROM flightroute WHERE flightroute_id = $1";
+            sqlx::query(query)
+                .bind(self.flightroute_id)
+                .execute(&mut *transaction)
+                .await?;
+        } else if current.is_none() {
+            let Some(flightroute_callsign_id) = self.flightroute_callsign_id else {
+                return Err(AppError::Internal(format!(
+                    "no flightroute_callsign_id to restore flightroute_id {}",
+                    self.flightroute_id
+                )));
+            };
+            let query = "INSERT INTO flightroute(flightroute_id, flightroute_callsign_id, airport_origin_id, airport_midpoint_id, airport_destination_id) OVERRIDING SYSTEM VALUE VALUES ($1, $2, $3, $4, $5)";
+            sqlx::query(query)
+                .bind(self.flightroute_id)
+                .bind(flightroute_callsign_id)
+                .bind(old.origin)
+                .bind(old.midpoint)
+                .bind(old.destination)
+                .execute(&mut *transaction)
+                .await?;
+        } else {
+            let query = "UPDATE flightroute SET airport_origin_id = $1, airport_midpoint_id = $2, airport_destination_id = $3 WHERE flightroute_id = $4";
+            sqlx::query(query)
+                .bind(old.origin)
+                .bind(old.midpoint)
+                .bind(old.destination)
+                .bind(self.flightroute_id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        Ok(())
+    }
+}
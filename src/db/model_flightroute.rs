@@ -1,4 +1,3 @@
-use redis::{aio::Connection, AsyncCommands};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Postgres, Transaction};
 
@@ -6,9 +5,35 @@ use crate::{app_error::AppError, callsign::Callsign};
 
 use super::ModelAirport;
 
-#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// The airport ids of a flightroute, used to record the state before and after a change
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteIds {
+    pub origin: Option<i64>,
+    pub midpoint: Option<i64>,
+    pub destination: Option<i64>,
+}
+
+/// What to do with the midpoint airport of a flightroute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Midpoint<T> {
+    Unchanged,
+    Clear,
+    Set(T),
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct ModelFlightroute {
+    // The ids aren't part of the flightroute that the adsbdb api caches
+    #[serde(skip)]
     pub flightroute_id: i64,
+    #[serde(skip)]
+    pub flightroute_callsign_id: i64,
+    #[serde(skip)]
+    pub airport_origin_id: i64,
+    #[serde(skip)]
+    pub airport_midpoint_id: Option<i64>,
+    #[serde(skip)]
+    pub airport_destination_id: i64,
     pub callsign: String,
     pub callsign_iata: Option<String>,
     pub callsign_icao: Option<String>,
@@ -52,49 +77,65 @@ pub struct ModelFlightroute {
     pub destination_airport_name: String,
 }
 
+/// Changes are made inside the caller's transaction, so the cache keys from `CacheKey::flightroute()` need to be deleted once it has been committed
 impl ModelFlightroute {
     /// Query for a fully joined Option<ModelFlightRoute>
-    /// Don't return result, as issues with nulls in the database, that I can't be bothered to deal with at the moment
-    async fn _get(db: &mut Transaction<'_, Postgres>, callsign: &Callsign) -> Option<Self> {
+    /// A row which can't be decoded, due to nulls in the database, is treated as not found, as that doesn't affect the transaction.
+    /// Any other error is returned, as it will have aborted the transaction
+    async fn _get(
+        db: &mut Transaction<'_, Postgres>,
+        callsign: &Callsign,
+    ) -> Result<Option<Self>, AppError> {
         let query = match callsign {
             Callsign::Iata(_) => Self::get_query_iata(),
             Callsign::Icao(_) => Self::get_query_icao(),
             Callsign::Other(_) => Self::get_query_callsign(),
         };
+        let decoded = |result: Result<Option<Self>, sqlx::Error>| match result {
+            Err(sqlx::Error::ColumnDecode { .. }) => Ok(None),
+            result => result,
+        };
 
         match callsign {
-            Callsign::Other(callsign) => sqlx::query_as::<_, Self>(query)
-                .bind(callsign)
-                .fetch_optional(&mut *db)
-                .await
-                .unwrap_or(None),
-            Callsign::Iata(x) | Callsign::Icao(x) => {
-                if let Ok(flightroute) = sqlx::query_as::<_, Self>(query)
-                    .bind(&x.0)
-                    .bind(&x.1)
+            Callsign::Other(callsign) => Ok(decoded(
+                sqlx::query_as::<_, Self>(query)
+                    .bind(callsign)
                     .fetch_optional(&mut *db)
-                    .await
-                {
-                    if let Some(flightroute) = flightroute {
-                        Some(flightroute)
-                    } else {
-                        sqlx::query_as::<_, Self>(Self::get_query_callsign())
-                            .bind(format!("{}{}", x.0, x.1))
-                            .fetch_optional(&mut *db)
-                            .await
-                            .unwrap_or(None)
-                    }
-                } else {
-                    None
+                    .await,
+            )?),
+            Callsign::Iata(x) | Callsign::Icao(x) => {
+                let flightroute = decoded(
+                    sqlx::query_as::<_, Self>(query)
+                        .bind(&x.0)
+                        .bind(&x.1)
+                        .fetch_optional(&mut *db)
+                        .await,
+                )?;
+                if flightroute.is_some() {
+                    return Ok(flightroute);
                 }
+                Ok(decoded(
+                    sqlx::query_as::<_, Self>(Self::get_query_callsign())
+                        .bind(format!("{}{}", x.0, x.1))
+                        .fetch_optional(&mut *db)
+                        .await,
+                )?)
             }
         }
     }
 
+    /// Get a flightroute from within an existing transaction, so that uncommitted changes are visible
+    pub async fn get_transaction(
+        transaction: &mut Transaction<'_, Postgres>,
+        callsign: &Callsign,
+    ) -> Result<Option<Self>, AppError> {
+        Self::_get(transaction, callsign).await
+    }
+
     // Why is this a transaction?
     pub async fn get(db: &PgPool, callsign: &Callsign) -> Result<Option<Self>, AppError> {
         let mut transaction = db.begin().await?;
-        let output = Self::_get(&mut transaction, callsign).await;
+        let output = Self::_get(&mut transaction, callsign).await?;
         transaction.commit().await?;
         Ok(output)
     }
@@ -104,6 +145,10 @@ impl ModelFlightroute {
         r"
 SELECT
     fl.flightroute_id,
+    fl.flightroute_callsign_id,
+    fl.airport_origin_id,
+    fl.airport_midpoint_id,
+    fl.airport_destination_id,
     $1 AS callsign,
     NULL AS callsign_iata,
     NULL AS callsign_icao,
@@ -163,6 +208,10 @@ WHERE fci.callsign = $1 LIMIT 1"
         r"
 SELECT
     fl.flightroute_id,
+    fl.flightroute_callsign_id,
+    fl.airport_origin_id,
+    fl.airport_midpoint_id,
+    fl.airport_destination_id,
     concat($1,$2) as callsign,
     concat(ai.iata_prefix, (SELECT callsign FROM flightroute_callsign_inner WHERE flightroute_callsign_inner_id = iata_prefix_id)) AS callsign_iata,
     concat(ai.icao_prefix, (SELECT callsign FROM flightroute_callsign_inner WHERE flightroute_callsign_inner_id = icao_prefix_id)) AS callsign_icao,
@@ -227,6 +276,10 @@ AND
         r"
 SELECT
     fl.flightroute_id,
+    fl.flightroute_callsign_id,
+    fl.airport_origin_id,
+    fl.airport_midpoint_id,
+    fl.airport_destination_id,
     concat($1,$2) as callsign,
     concat(ai.iata_prefix, (SELECT callsign FROM flightroute_callsign_inner WHERE flightroute_callsign_inner_id = iata_prefix_id)) AS callsign_iata,
     concat(ai.icao_prefix, (SELECT callsign FROM flightroute_callsign_inner WHERE flightroute_callsign_inner_id = icao_prefix_id)) AS callsign_icao,
@@ -282,31 +335,194 @@ AND
     flc.icao_prefix_id = (SELECT flightroute_callsign_inner_id FROM flightroute_callsign_inner WHERE callsign = $2 LIMIT 1)"
     }
 
-    /// Update self, with new origin or destination, or both, and clear cache
+    /// The current airport ids of self
+    pub const fn route_ids(&self) -> RouteIds {
+        RouteIds {
+            origin: Some(self.airport_origin_id),
+            midpoint: self.airport_midpoint_id,
+            destination: Some(self.airport_destination_id),
+        }
+    }
+
+    /// Update self, with new origin, destination, and midpoint
     pub async fn update(
         &self,
-        postgres: &PgPool,
-        redis: &mut Connection,
-        origin: ModelAirport,
-        destination: ModelAirport,
+        transaction: &mut Transaction<'_, Postgres>,
+        origin: &ModelAirport,
+        midpoint: &Midpoint<ModelAirport>,
+        destination: &ModelAirport,
     ) -> Result<(), AppError> {
-        let query = "UPDATE flightroute SET airport_origin_id = $1, airport_destination_id = $2 WHERE flightroute_id = $3";
+        let midpoint_id = match midpoint {
+            Midpoint::Unchanged => {
+                let query = "UPDATE flightroute SET airport_origin_id = $1, airport_destination_id = $2 WHERE flightroute_id = $3";
+                sqlx::query(query)
+                    .bind(origin.airport_id)
+                    .bind(destination.airport_id)
+                    .bind(self.flightroute_id)
+                    .execute(&mut *transaction)
+                    .await?;
+                return Ok(());
+            }
+            Midpoint::Clear => None,
+            Midpoint::Set(airport) => Some(airport.airport_id),
+        };
 
+        let query = "UPDATE flightroute SET airport_origin_id = $1, airport_midpoint_id = $2, airport_destination_id = $3 WHERE flightroute_id = $4";
         sqlx::query(query)
             .bind(origin.airport_id)
+            .bind(midpoint_id)
             .bind(destination.airport_id)
             .bind(self.flightroute_id)
-            .execute(postgres)
+            .execute(&mut *transaction)
+            .await?;
+        Ok(())
+    }
+
+    /// Set the midpoint of self back to NULL
+    pub async fn clear_midpoint(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), AppError> {
+        let query = "UPDATE flightroute SET airport_midpoint_id = NULL WHERE flightroute_id = $1";
+        sqlx::query(query)
+            .bind(self.flightroute_id)
+            .execute(&mut *transaction)
             .await?;
+        Ok(())
+    }
 
-        if let Some(iata) = self.callsign_iata.as_ref() {
-            redis.del(format!("callsign::{iata}")).await?;
+    /// Delete self.
+    /// The flightroute_callsign row is left in place, as a rollback restores the flightroute against its original flightroute_callsign_id, and `insert` reuses it
+    pub async fn delete(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), AppError> {
+        let query = "DELETE FROM flightroute WHERE flightroute_id = $1";
+        sqlx::query(query)
+            .bind(self.flightroute_id)
+            .execute(&mut *transaction)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the id of a flightroute_callsign_inner, inserting it if it doesn't exist yet
+    async fn get_or_insert_inner(
+        transaction: &mut Transaction<'_, Postgres>,
+        callsign: &str,
+    ) -> Result<i64, AppError> {
+        let query = "SELECT flightroute_callsign_inner_id FROM flightroute_callsign_inner WHERE callsign = $1";
+        if let Some(id) = sqlx::query_scalar::<_, i64>(query)
+            .bind(callsign)
+            .fetch_optional(&mut *transaction)
+            .await?
+        {
+            return Ok(id);
         }
+        let query = "INSERT INTO flightroute_callsign_inner(callsign) VALUES ($1) RETURNING flightroute_callsign_inner_id";
+        Ok(sqlx::query_scalar::<_, i64>(query)
+            .bind(callsign)
+            .fetch_one(&mut *transaction)
+            .await?)
+    }
 
-        if let Some(icao) = self.callsign_icao.as_ref() {
-            redis.del(format!("callsign::{icao}")).await?;
+    /// Get the airline_id for an ICAO or IATA callsign prefix.
+    /// IATA prefixes aren't unique, so a prefix shared by more than one airline is refused, rather than picking one of them
+    async fn get_airline_id(
+        transaction: &mut Transaction<'_, Postgres>,
+        callsign: &Callsign,
+    ) -> Result<Option<i64>, AppError> {
+        let (query, prefix) = match callsign {
+            Callsign::Icao(x) => (
+                "SELECT airline_id FROM airline WHERE icao_prefix = $1",
+                &x.0,
+            ),
+            Callsign::Iata(x) => (
+                "SELECT airline_id FROM airline WHERE iata_prefix = $1",
+                &x.0,
+            ),
+            Callsign::Other(_) => return Ok(None),
+        };
+        let airline_ids = sqlx::query_scalar::<_, i64>(query)
+            .bind(prefix)
+            .fetch_all(&mut *transaction)
+            .await?;
+        match airline_ids.as_slice() {
+            [] => Ok(None),
+            [airline_id] => Ok(Some(*airline_id)),
+            _ => Err(AppError::AmbiguousAirline(format!(
+                "{prefix} matches {} airlines",
+                airline_ids.len()
+            ))),
         }
+    }
 
-        Ok(())
+    /// Get the id of the flightroute_callsign for a callsign, reusing an existing one, such as that left behind by a delete, before inserting a new one
+    async fn get_or_insert_callsign(
+        transaction: &mut Transaction<'_, Postgres>,
+        callsign_id: i64,
+        airline_id: Option<i64>,
+        prefix_id: Option<i64>,
+    ) -> Result<i64, AppError> {
+        let query = r"
+SELECT
+    flightroute_callsign_id
+FROM flightroute_callsign
+WHERE
+    (callsign_id = $1 AND airline_id IS NOT DISTINCT FROM $2)
+OR
+    (airline_id = $2 AND icao_prefix_id = $3)
+ORDER BY flightroute_callsign_id
+LIMIT 1";
+        if let Some(id) = sqlx::query_scalar::<_, i64>(query)
+            .bind(callsign_id)
+            .bind(airline_id)
+            .bind(prefix_id)
+            .fetch_optional(&mut *transaction)
+            .await?
+        {
+            return Ok(id);
+        }
+        let query = "INSERT INTO flightroute_callsign(callsign_id, airline_id, iata_prefix_id, icao_prefix_id) VALUES ($1, $2, $3, $3) RETURNING flightroute_callsign_id";
+        Ok(sqlx::query_scalar::<_, i64>(query)
+            .bind(callsign_id)
+            .bind(airline_id)
+            .bind(prefix_id)
+            .fetch_one(&mut *transaction)
+            .await?)
+    }
+
+    /// Create a new flightroute, and the callsign rows it needs, reusing any that already exist.
+    /// An ICAO or IATA callsign is linked to its airline, with the numeric part used as both the ICAO and IATA prefix,
+    /// any other callsign, or one whose airline can't be found, is stored as a bare callsign
+    pub async fn insert(
+        transaction: &mut Transaction<'_, Postgres>,
+        callsign: &Callsign,
+        origin: &ModelAirport,
+        midpoint: &Midpoint<ModelAirport>,
+        destination: &ModelAirport,
+    ) -> Result<i64, AppError> {
+        let airline_id = Self::get_airline_id(transaction, callsign).await?;
+        let callsign_id = Self::get_or_insert_inner(transaction, &callsign.to_string()).await?;
+        let prefix_id = match (callsign, airline_id) {
+            (Callsign::Icao(x) | Callsign::Iata(x), Some(_)) => {
+                Some(Self::get_or_insert_inner(transaction, &x.1).await?)
+            }
+            _ => None,
+        };
+        let flightroute_callsign_id =
+            Self::get_or_insert_callsign(transaction, callsign_id, airline_id, prefix_id).await?;
+
+        let midpoint_id = match midpoint {
+            Midpoint::Set(airport) => Some(airport.airport_id),
+            Midpoint::Unchanged | Midpoint::Clear => None,
+        };
+        let query = "INSERT INTO flightroute(flightroute_callsign_id, airport_origin_id, airport_midpoint_id, airport_destination_id) VALUES ($1, $2, $3, $4) RETURNING flightroute_id";
+        Ok(sqlx::query_scalar::<_, i64>(query)
+            .bind(flightroute_callsign_id)
+            .bind(origin.airport_id)
+            .bind(midpoint_id)
+            .bind(destination.airport_id)
+            .fetch_one(&mut *transaction)
+            .await?)
     }
 }
@@ -0,0 +1,648 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{app_error::AppError, db::Midpoint};
+
+const DEFAULT_INPUT: &str = "./input.csv";
+
+/// Used in the midpoint column to clear the midpoint airport back to NULL
+pub const CLEAR_MIDPOINT: &str = "-";
+
+/// The format of an input, detected from the file extension unless set explicitly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Csv,
+    Json,
+    JsonLines,
+}
+
+impl InputFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "jsonl" | "ndjson" => Some(Self::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+/// Where in the input a record came from, a line for csv and json lines, an index into the array for json
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Position {
+    Line(u64),
+    Index(usize),
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Line(line) => write!(f, "line {line}"),
+            Self::Index(index) => write!(f, "index {index}"),
+        }
+    }
+}
+
+impl Serialize for Position {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Where to read input records from, `-` is used to mean stdin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    Stdin,
+    File(PathBuf),
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Stdin => write!(f, "stdin"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl From<&str> for InputSource {
+    fn from(value: &str) -> Self {
+        if value == "-" {
+            Self::Stdin
+        } else {
+            Self::File(PathBuf::from(value))
+        }
+    }
+}
+
+impl InputSource {
+    /// Sources from the command line arguments, else from the INPUT_PATH env, else the default `./input.csv`
+    pub fn get(args: &[String], input_path: Option<&str>) -> Vec<Self> {
+        if !args.is_empty() {
+            return args.iter().map(|i| Self::from(i.as_str())).collect();
+        }
+        input_path.map_or_else(
+            || vec![Self::from(DEFAULT_INPUT)],
+            |input_path| {
+                input_path
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|i| !i.is_empty())
+                    .map(Self::from)
+                    .collect()
+            },
+        )
+    }
+
+    /// The path a report file should be written to, next to the input file, or in the current directory for stdin
+    pub fn report_path(&self, extension: &str) -> PathBuf {
+        match self {
+            Self::Stdin => Path::new("stdin").with_extension(extension),
+            Self::File(path) => path.with_extension(extension),
+        }
+    }
+
+    /// Use the given format, else detect it from the file extension, defaulting to csv
+    pub fn format(&self, format: Option<InputFormat>) -> InputFormat {
+        format.unwrap_or_else(|| match self {
+            Self::Stdin => InputFormat::Csv,
+            Self::File(path) => path
+                .extension()
+                .and_then(|i| i.to_str())
+                .and_then(InputFormat::parse)
+                .unwrap_or(InputFormat::Csv),
+        })
+    }
+
+    /// Load, and parse, the input
+    pub fn load(&self, format: Option<InputFormat>) -> Result<Parsed, AppError> {
+        let text = match self {
+            Self::Stdin => io::read_to_string(io::stdin())?,
+            Self::File(path) => std::fs::read_to_string(path)?,
+        };
+        Ok(match self.format(format) {
+            InputFormat::Csv => parse_csv(&text),
+            InputFormat::Json => parse_json(&text),
+            InputFormat::JsonLines => parse_json_lines(&text),
+        })
+    }
+
+    /// Write rows, in the same csv shape as the input, to `[input].undo.[run_id].csv`, next to the input file.
+    /// An existing undo file is never overwritten, so re-running an input can't replace the original backup
+    pub fn write_undo(
+        &self,
+        run_id: &str,
+        rows: &[UpdatedFlightroute],
+    ) -> Result<PathBuf, AppError> {
+        let path = self.report_path(&format!("undo.{run_id}.csv"));
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        write_rows(file, InputFormat::Csv, rows)?;
+        Ok(path)
+    }
+}
+
+/// Write rows in any of the input formats, so that they can be read back in as input
+pub fn write_rows<W: io::Write>(
+    mut writer: W,
+    format: InputFormat,
+    rows: &[UpdatedFlightroute],
+) -> Result<(), AppError> {
+    match format {
+        InputFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            for row in rows {
+                csv_writer.serialize(row)?;
+            }
+            csv_writer.flush()?;
+        }
+        InputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, rows)?;
+            writeln!(writer)?;
+        }
+        InputFormat::JsonLines => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, row)?;
+                writeln!(writer)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What to do with the flightroute for a callsign, defaults to update when empty or missing
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    #[default]
+    Update,
+    Delete,
+    ClearMidpoint,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let disp = match self {
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::ClearMidpoint => "clear_midpoint",
+        };
+        write!(f, "{disp}")
+    }
+}
+
+/// Origin and destination are only required for the update action
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UpdatedFlightroute {
+    pub callsign: String,
+    #[serde(default)]
+    pub origin: String,
+    #[serde(default)]
+    pub destination: String,
+    #[serde(default)]
+    pub midpoint: Option<String>,
+    #[serde(default)]
+    pub action: Option<Action>,
+}
+
+impl UpdatedFlightroute {
+    pub fn action(&self) -> Action {
+        self.action.unwrap_or_default()
+    }
+
+    /// An empty, or missing, midpoint leaves the current midpoint unchanged, `-` clears it
+    pub fn midpoint(&self) -> Midpoint<&str> {
+        match self.midpoint.as_deref().map(str::trim) {
+            None | Some("") => Midpoint::Unchanged,
+            Some(CLEAR_MIDPOINT) => Midpoint::Clear,
+            Some(midpoint) => Midpoint::Set(midpoint),
+        }
+    }
+}
+
+/// A successfully parsed record, and where it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputRow {
+    pub position: Position,
+    pub flightroute: UpdatedFlightroute,
+}
+
+/// A record that couldn't be parsed, with the raw text of the record, and the parse error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub position: Position,
+    pub raw: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Parsed {
+    pub rows: Vec<InputRow>,
+    pub rejected: Vec<Rejected>,
+}
+
+/// Parse csv text, any record with a missing or extra column, or that fails to deserialize, is rejected rather than dropped
+fn parse_csv(text: &str) -> Parsed {
+    let lines = text.lines().collect::<Vec<_>>();
+    let raw = |line: u64| {
+        usize::try_from(line)
+            .ok()
+            .and_then(|line| lines.get(line.saturating_sub(1)))
+            .map_or_else(String::new, |raw| (*raw).to_owned())
+    };
+
+    let mut parsed = Parsed::default();
+    let mut rdr = csv::Reader::from_reader(text.as_bytes());
+    let headers = match rdr.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            parsed.rejected.push(Rejected {
+                position: Position::Line(1),
+                raw: raw(1),
+                error: e.to_string(),
+            });
+            return parsed;
+        }
+    };
+
+    let mut record = csv::StringRecord::new();
+    loop {
+        let next_line = rdr.position().line();
+        match rdr.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => {
+                let line = record.position().map_or(next_line, csv::Position::line);
+                match record.deserialize::<UpdatedFlightroute>(Some(&headers)) {
+                    Ok(flightroute) => parsed.rows.push(InputRow {
+                        position: Position::Line(line),
+                        flightroute,
+                    }),
+                    Err(e) => parsed.rejected.push(Rejected {
+                        position: Position::Line(line),
+                        raw: raw(line),
+                        error: e.to_string(),
+                    }),
+                }
+            }
+            Err(e) => {
+                let line = e.position().map_or(next_line, csv::Position::line);
+                parsed.rejected.push(Rejected {
+                    position: Position::Line(line),
+                    raw: raw(line),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    parsed
+}
+
+/// Parse a json array of records, each record that fails to deserialize is rejected with its index
+fn parse_json(text: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    match serde_json::from_str::<Vec<serde_json::Value>>(text) {
+        Ok(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                match serde_json::from_value::<UpdatedFlightroute>(value.clone()) {
+                    Ok(flightroute) => parsed.rows.push(InputRow {
+                        position: Position::Index(index),
+                        flightroute,
+                    }),
+                    Err(e) => parsed.rejected.push(Rejected {
+                        position: Position::Index(index),
+                        raw: value.to_string(),
+                        error: e.to_string(),
+                    }),
+                }
+            }
+        }
+        Err(e) => parsed.rejected.push(Rejected {
+            position: Position::Line(u64::try_from(e.line()).unwrap_or_default()),
+            raw: String::new(),
+            error: e.to_string(),
+        }),
+    }
+    parsed
+}
+
+/// Parse json lines, one record per line, blank lines are ignored
+fn parse_json_lines(text: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    for (line, raw) in (1..).zip(text.lines()) {
+        if raw.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<UpdatedFlightroute>(raw) {
+            Ok(flightroute) => parsed.rows.push(InputRow {
+                position: Position::Line(line),
+                flightroute,
+            }),
+            Err(e) => parsed.rejected.push(Rejected {
+                position: Position::Line(line),
+                raw: raw.to_owned(),
+                error: e.to_string(),
+            }),
+        }
+    }
+    parsed
+}
+
+/// Run tests with
+///
+/// cargo watch -q -c -w src/ -x 'test input_ -- --nocapture'
+#[cfg(test)]
+#[allow(clippy::pedantic, clippy::nursery, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_source_get() {
+        // FIXTURES
+        let args = vec!["a.csv".to_owned(), "-".to_owned(), "b.csv".to_owned()];
+
+        // ACTION
+        let result = InputSource::get(&args, Some("c.csv"));
+
+        // CHECK
+        assert_eq!(
+            result,
+            vec![
+                InputSource::File(PathBuf::from("a.csv")),
+                InputSource::Stdin,
+                InputSource::File(PathBuf::from("b.csv")),
+            ]
+        );
+
+        // ACTION
+        let result = InputSource::get(&[], Some("c.csv, d.csv"));
+
+        // CHECK
+        assert_eq!(
+            result,
+            vec![
+                InputSource::File(PathBuf::from("c.csv")),
+                InputSource::File(PathBuf::from("d.csv")),
+            ]
+        );
+
+        // ACTION
+        let result = InputSource::get(&[], None);
+
+        // CHECK
+        assert_eq!(
+            result,
+            vec![InputSource::File(PathBuf::from("./input.csv"))]
+        );
+    }
+
+    #[test]
+    fn input_source_report_path() {
+        // ACTION
+        let result = InputSource::from("./data/input.csv").report_path("report.csv");
+
+        // CHECK
+        assert_eq!(result, PathBuf::from("./data/input.report.csv"));
+
+        // ACTION
+        let result = InputSource::from("-").report_path("report.json");
+
+        // CHECK
+        assert_eq!(result, PathBuf::from("stdin.report.json"));
+    }
+
+    #[test]
+    fn input_source_write_undo() {
+        // SETUP
+        let dir = std::env::temp_dir().join("adsbdb_updater_input_source_write_undo");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = InputSource::File(dir.join("input.csv"));
+        let rows = vec![
+            UpdatedFlightroute {
+                callsign: "SWA2238".to_owned(),
+                origin: "KPDX".to_owned(),
+                destination: "KPHX".to_owned(),
+                midpoint: Some(CLEAR_MIDPOINT.to_owned()),
+                action: None,
+            },
+            UpdatedFlightroute {
+                callsign: "N123AB".to_owned(),
+                origin: String::new(),
+                destination: String::new(),
+                midpoint: None,
+                action: Some(Action::Delete),
+            },
+        ];
+
+        // ACTION
+        let path = source.write_undo("abc", &rows).unwrap();
+        let result = parse_csv(&std::fs::read_to_string(&path).unwrap());
+        let overwrite = source.write_undo("abc", &[]);
+
+        // CHECK
+        assert_eq!(path, dir.join("input.undo.abc.csv"));
+        assert!(overwrite.is_err());
+        assert!(result.rejected.is_empty());
+        assert_eq!(
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.flightroute)
+                .collect::<Vec<_>>(),
+            rows
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn input_write_rows() {
+        // FIXTURES
+        let rows = vec![
+            UpdatedFlightroute {
+                callsign: "SWA2238".to_owned(),
+                origin: "PDX".to_owned(),
+                destination: "PHX".to_owned(),
+                midpoint: Some("SMF".to_owned()),
+                action: None,
+            },
+            UpdatedFlightroute {
+                callsign: "N123AB".to_owned(),
+                origin: String::new(),
+                destination: String::new(),
+                midpoint: None,
+                action: Some(Action::Delete),
+            },
+        ];
+
+        for (format, parse) in [
+            (InputFormat::Csv, parse_csv as fn(&str) -> Parsed),
+            (InputFormat::Json, parse_json),
+            (InputFormat::JsonLines, parse_json_lines),
+        ] {
+            // ACTION
+            let mut output = vec![];
+            write_rows(&mut output, format, &rows).unwrap();
+            let result = parse(&String::from_utf8(output).unwrap());
+
+            // CHECK
+            assert!(result.rejected.is_empty());
+            assert_eq!(
+                result
+                    .rows
+                    .into_iter()
+                    .map(|row| row.flightroute)
+                    .collect::<Vec<_>>(),
+                rows
+            );
+        }
+    }
+
+    #[test]
+    fn input_parse_csv_valid() {
+        // FIXTURES
+        let text = "callsign,origin,destination\nSWA2238,PDX,PHX\nDAL2393,SFO,SEA\n";
+
+        // ACTION
+        let result = parse_csv(text);
+
+        // CHECK
+        assert!(result.rejected.is_empty());
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(result.rows[0].position, Position::Line(2));
+        assert_eq!(result.rows[1].position, Position::Line(3));
+        assert_eq!(
+            result.rows[1].flightroute,
+            UpdatedFlightroute {
+                callsign: "DAL2393".to_owned(),
+                origin: "SFO".to_owned(),
+                destination: "SEA".to_owned(),
+                midpoint: None,
+                action: None,
+            }
+        );
+    }
+
+    #[test]
+    fn input_parse_csv_action() {
+        // FIXTURES
+        let text = "callsign,origin,destination,midpoint,action\nSWA2238,PDX,PHX,,\nSWA2239,,,,delete\nSWA2240,,,,clear_midpoint\nSWA2241,PDX,PHX,,update\nSWA2242,,,,remove\n";
+
+        // ACTION
+        let result = parse_csv(text);
+
+        // CHECK
+        assert_eq!(result.rows.len(), 4);
+        assert_eq!(result.rows[0].flightroute.action(), Action::Update);
+        assert_eq!(result.rows[1].flightroute.action(), Action::Delete);
+        assert_eq!(result.rows[2].flightroute.action(), Action::ClearMidpoint);
+        assert_eq!(result.rows[3].flightroute.action(), Action::Update);
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].position, Position::Line(6));
+    }
+
+    #[test]
+    fn input_parse_csv_midpoint() {
+        // FIXTURES
+        let text = "callsign,origin,destination,midpoint\nSWA2238,PDX,PHX,\nSWA2239,PDX,PHX,SMF\nSWA2240,PDX,PHX,-\n";
+
+        // ACTION
+        let result = parse_csv(text);
+
+        // CHECK
+        assert!(result.rejected.is_empty());
+        assert_eq!(result.rows[0].flightroute.midpoint(), Midpoint::Unchanged);
+        assert_eq!(result.rows[1].flightroute.midpoint(), Midpoint::Set("SMF"));
+        assert_eq!(result.rows[2].flightroute.midpoint(), Midpoint::Clear);
+    }
+
+    #[test]
+    fn input_parse_csv_rejected() {
+        // FIXTURES
+        let text =
+            "callsign,origin,destination\nSWA2238,PDX\nDAL2393,SFO,SEA\nDAL1045,LAX,SEA,SFO\n";
+
+        // ACTION
+        let result = parse_csv(text);
+
+        // CHECK
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0].position, Position::Line(3));
+        assert_eq!(result.rejected.len(), 2);
+        assert_eq!(result.rejected[0].position, Position::Line(2));
+        assert_eq!(result.rejected[0].raw, "SWA2238,PDX");
+        assert_eq!(result.rejected[1].position, Position::Line(4));
+        assert_eq!(result.rejected[1].raw, "DAL1045,LAX,SEA,SFO");
+    }
+
+    #[test]
+    fn input_source_format() {
+        // ACTION
+        let result01 = InputSource::from("input.csv").format(None);
+        let result02 = InputSource::from("input.json").format(None);
+        let result03 = InputSource::from("input.jsonl").format(None);
+        let result04 = InputSource::from("input.ndjson").format(None);
+        let result05 = InputSource::from("input.txt").format(None);
+        let result06 = InputSource::from("-").format(None);
+        let result07 = InputSource::from("input.csv").format(Some(InputFormat::JsonLines));
+
+        // CHECK
+        assert_eq!(result01, InputFormat::Csv);
+        assert_eq!(result02, InputFormat::Json);
+        assert_eq!(result03, InputFormat::JsonLines);
+        assert_eq!(result04, InputFormat::JsonLines);
+        assert_eq!(result05, InputFormat::Csv);
+        assert_eq!(result06, InputFormat::Csv);
+        assert_eq!(result07, InputFormat::JsonLines);
+    }
+
+    #[test]
+    fn input_parse_json() {
+        // FIXTURES
+        let text = r#"[
+            {"callsign": "SWA2238", "origin": "PDX", "destination": "PHX"},
+            {"origin": "SFO", "destination": "SEA"},
+            {"callsign": "DAL1045", "origin": "LAX", "destination": "SEA"}
+        ]"#;
+
+        // ACTION
+        let result = parse_json(text);
+
+        // CHECK
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(result.rows[0].position, Position::Index(0));
+        assert_eq!(result.rows[1].position, Position::Index(2));
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].position, Position::Index(1));
+        assert!(result.rejected[0].error.contains("callsign"));
+
+        // ACTION
+        let result = parse_json("{}");
+
+        // CHECK
+        assert!(result.rows.is_empty());
+        assert_eq!(result.rejected.len(), 1);
+    }
+
+    #[test]
+    fn input_parse_json_lines() {
+        // FIXTURES
+        let text = "{\"callsign\": \"SWA2238\", \"origin\": \"PDX\", \"destination\": \"PHX\"}\n\n{\"callsign\": \"DAL2393\"\n{\"callsign\": \"DAL2394\", \"action\": \"delete\"}\n";
+
+        // ACTION
+        let result = parse_json_lines(text);
+
+        // CHECK
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(result.rows[0].position, Position::Line(1));
+        assert_eq!(result.rows[1].position, Position::Line(4));
+        assert_eq!(result.rows[1].flightroute.action(), Action::Delete);
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].position, Position::Line(3));
+        assert_eq!(result.rejected[0].raw, "{\"callsign\": \"DAL2393\"");
+    }
+}
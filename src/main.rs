@@ -11,66 +11,265 @@
 // Only allow when debugging
 // #![allow(unused, clippy::todo)]
 
-use std::io;
-
 use app_error::AppError;
-use parse_env::AppEnv;
-use serde::{Deserialize, Serialize};
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand, InputArgs, OutputArgs};
+use parse_env::{AppEnv, ParseMode};
+use sqlx::PgPool;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt, Layer,
+    Registry,
+};
 
 use crate::{
-    callsign::{Callsign, Validate},
-    db::{ModelAirport, ModelFlightroute},
+    db::ModelAudit,
+    input::{InputSource, Parsed, UpdatedFlightroute},
+    report::{Report, RowReport},
+    updater::Run,
 };
 mod app_error;
 mod callsign;
+mod cli;
 mod db;
+mod input;
 mod n_number;
 mod parse_env;
+mod report;
+mod rollback;
+mod updater;
 
+const LOG_FILE: &str = "adsbdb_updater.log";
+
+/// A log layer, plain or json, writing to the given writer
+fn log_layer<W>(json: bool, ansi: bool, writer: W) -> Box<dyn Layer<Registry> + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(ansi)
+        .with_writer(writer);
+    if json {
+        layer.json().boxed()
+    } else {
+        layer.boxed()
+    }
+}
+
+/// Log to stdout and, with LOG_DIR, to a daily rolling file in that directory, as json with LOG_JSON.
+/// The file is written to directly, rather than from a background thread, so nothing is lost when exiting early.
+/// A LOG_DIR that can't be created, or written to, is reported as an invalid env
 fn setup_tracing(app_env: &AppEnv) {
-    tracing_subscriber::fmt()
-        .with_max_level(app_env.log_level)
+    let mut layers = vec![log_layer(app_env.log_json, true, std::io::stdout)];
+    if let Some(log_dir) = app_env.log_dir.as_ref() {
+        let writer = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_FILE)
+            .build(log_dir)
+            .unwrap_or_else(|e| {
+                AppEnv::exit_invalid("LOG_DIR", "a writable directory", e.to_string())
+            });
+        layers.push(log_layer(app_env.log_json, false, writer));
+    }
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(LevelFilter::from_level(app_env.log_level))
         .init();
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct UpdatedFlightroute {
-    callsign: String,
-    origin: String,
-    destination: String,
+/// Load, and parse, every input, the cli inputs and format take precedence over INPUT_PATH and INPUT_FORMAT.
+/// Rejected records are logged unless lenient, and refused outright with REFUSE_PARSE_ERRORS
+fn load_inputs(
+    app_env: &AppEnv,
+    input: &InputArgs,
+) -> Result<Vec<(InputSource, Parsed)>, AppError> {
+    let mut inputs = vec![];
+    for source in InputSource::get(&input.inputs, app_env.input_path.as_deref()) {
+        let parsed = source.load(input.format.or(app_env.input_format))?;
+        inputs.push((source, parsed));
+    }
+
+    if app_env.parse_mode != ParseMode::Lenient {
+        for (source, parsed) in &inputs {
+            for rejected in &parsed.rejected {
+                tracing::warn!(
+                    %source,
+                    position = %rejected.position,
+                    raw = rejected.raw,
+                    "{}",
+                    rejected.error
+                );
+            }
+        }
+    }
+
+    if app_env.parse_mode == ParseMode::Refuse
+        && inputs.iter().any(|(_, parsed)| !parsed.rejected.is_empty())
+    {
+        for (source, parsed) in &inputs {
+            for rejected in &parsed.rejected {
+                println!(
+                    "\x1b[31m{source} {}: {}; {}\x1b[0m",
+                    rejected.position, rejected.raw, rejected.error
+                );
+            }
+        }
+        std::process::exit(1);
+    }
+    Ok(inputs)
+}
+
+/// Write rows to the output file, else stdout
+fn write_output(output: &OutputArgs, rows: &[UpdatedFlightroute]) -> Result<(), AppError> {
+    match output.output.as_ref() {
+        Some(path) => input::write_rows(std::fs::File::create(path)?, output.to, rows),
+        None => input::write_rows(std::io::stdout().lock(), output.to, rows),
+    }
+}
+
+/// `apply`, write every input to Postgres, and invalidate the cache
+async fn apply(app_env: &AppEnv, postgres: &PgPool, input: &InputArgs) -> Result<(), AppError> {
+    let inputs = load_inputs(app_env, input)?;
+    let mut redis = db::get_connection(app_env).await?;
+    ModelAudit::create_table(postgres).await?;
+    let run_id = ModelAudit::new_run_id(postgres).await?;
+    tracing::info!("run_id: {run_id}");
+
+    let mut failed = false;
+    for (source, parsed) in &inputs {
+        tracing::info!("processing {source}");
+        let mut report = Report::default();
+        if app_env.parse_mode == ParseMode::Strict {
+            for rejected in &parsed.rejected {
+                report.push(RowReport::from(rejected));
+            }
+        }
+
+        let undo = updater::undo(app_env, postgres, &parsed.rows).await?;
+        let undo_path = source.write_undo(&run_id, &undo)?;
+        tracing::info!("undo file: {}", undo_path.display());
+
+        let source_name = source.to_string();
+        let run = Run {
+            id: &run_id,
+            source: &source_name,
+        };
+        let applied = updater::apply(
+            app_env,
+            postgres,
+            &mut redis,
+            &run,
+            &parsed.rows,
+            &mut report,
+        )
+        .await;
+        report.write(source)?;
+
+        println!("{source}: {}", report.summary());
+        applied?;
+        if report.has_errors() || report.is_rolled_back() {
+            for row in report.errors() {
+                println!(
+                    "\x1b[31m{source} {}: {}\x1b[0m",
+                    row.position,
+                    row.message.as_deref().unwrap_or_default()
+                );
+            }
+            failed = true;
+            if app_env.fail_fast {
+                break;
+            }
+        }
+    }
+    println!("run_id: {run_id}");
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `check`, print the changes every input would make, without writing anything
+async fn check(app_env: &AppEnv, postgres: &PgPool, input: &InputArgs) -> Result<(), AppError> {
+    for (_, parsed) in &load_inputs(app_env, input)? {
+        updater::dry_run(app_env, postgres, &parsed.rows).await?;
+    }
+    Ok(())
+}
+
+/// `export`, write the current route of every callsign in the inputs
+async fn export(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    input: &InputArgs,
+    output: &OutputArgs,
+) -> Result<(), AppError> {
+    let rows = load_inputs(app_env, input)?
+        .into_iter()
+        .flat_map(|(_, parsed)| parsed.rows)
+        .collect::<Vec<_>>();
+    write_output(output, &updater::export(postgres, &rows).await?)
 }
 
-fn load_data_into_vec() -> Result<Vec<UpdatedFlightroute>, AppError> {
-    let input = "./input.csv";
-    let file_input = std::fs::File::open(input)?;
-    let reader = io::BufReader::new(&file_input);
-    let mut rdr = csv::Reader::from_reader(reader);
-    Ok(rdr
-        .deserialize::<UpdatedFlightroute>()
-        .flatten()
-        .collect::<Vec<UpdatedFlightroute>>())
+/// `convert`, write every input in another format
+fn convert(app_env: &AppEnv, input: &InputArgs, output: &OutputArgs) -> Result<(), AppError> {
+    let rows = load_inputs(app_env, input)?
+        .into_iter()
+        .flat_map(|(_, parsed)| parsed.rows)
+        .map(|row| row.flightroute)
+        .collect::<Vec<_>>();
+    write_output(output, &rows)
+}
+
+/// `rollback`, revert every change made by a previous run
+async fn rollback(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    run_id: &str,
+    force: bool,
+) -> Result<(), AppError> {
+    let mut redis = db::get_connection(app_env).await?;
+    ModelAudit::create_table(postgres).await?;
+    if !rollback::rollback(app_env, postgres, &mut redis, run_id, force).await? {
+        std::process::exit(1);
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    let app_env = parse_env::AppEnv::get_env();
+    let cli = Cli::parse();
+    let mut app_env = AppEnv::get_env(
+        cli.env_file.as_deref(),
+        cli.config.as_deref(),
+        cli.profile.as_deref(),
+    );
+    if let Some(log_level) = cli.log_level {
+        app_env.log_level = log_level;
+    }
     setup_tracing(&app_env);
-    let postgres = db::db_pool(&app_env).await?;
-    let mut redis = db::get_connection(&app_env).await?;
-
-    let flights_to_update = load_data_into_vec()?;
-
-    for i in flights_to_update {
-        let callsign = Callsign::validate(&i.callsign)?;
-        if let Some(flightroute) = ModelFlightroute::get(&postgres, &callsign).await? {
-            let original_airport = ModelAirport::get(&postgres, &i.origin).await?;
-            let destination_airport = ModelAirport::get(&postgres, &i.destination).await?;
-            if let (Some(origin), Some(destination)) = (original_airport, destination_airport) {
-                flightroute
-                    .update(&postgres, &mut redis, origin, destination)
-                    .await?;
-            }
+
+    match cli.command.unwrap_or_default() {
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => {
+            println!("{app_env}");
+            Ok(())
+        }
+        Command::Convert { input, output } => convert(&app_env, &input, &output),
+        Command::Apply(input) if !app_env.dry_run => {
+            apply(&app_env, &db::db_pool(&app_env).await?, &input).await
+        }
+        Command::Apply(input) | Command::Check(input) => {
+            check(&app_env, &db::db_pool(&app_env).await?, &input).await
+        }
+        Command::Show { callsigns } => {
+            updater::show(&db::db_pool(&app_env).await?, &callsigns).await
+        }
+        Command::Export { input, output } => {
+            export(&app_env, &db::db_pool(&app_env).await?, &input, &output).await
+        }
+        Command::Rollback { run_id, force } => {
+            rollback(&app_env, &db::db_pool(&app_env).await?, &run_id, force).await
         }
     }
-    Ok(())
 }
@@ -6,10 +6,9 @@
 
 // Honestly don't truly understand what is happening in most of these functions
 // But it seems to work as expected, although probably inefficient
-use std::fmt;
+use std::{fmt, sync::LazyLock};
 
 // use AppError, ModeS, NNumber, Validate};
-use once_cell::sync::Lazy;
 
 use crate::{
     app_error::AppError,
@@ -23,7 +22,7 @@ const ICAO_CHARSET: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ";
 const DIGITSET: &str = "0123456789";
 const CHARSET_LEN: usize = 24;
 
-pub static ALLCHARS: Lazy<String> = Lazy::new(|| format!("{ICAO_CHARSET}{DIGITSET}"));
+pub static ALLCHARS: LazyLock<String> = LazyLock::new(|| format!("{ICAO_CHARSET}{DIGITSET}"));
 
 const SUFFIX_SIZE: usize = 601;
 
@@ -169,7 +168,7 @@ pub fn mode_s_to_n_number(mode_s: &ModeS) -> Result<NNumber, AppError> {
     let mut rem = usize::from_str_radix(&mode_s.to_string()[1..], 16)? - 1;
 
     let calc_rem = |output: &mut String, rem: usize, bucket: Bucket| -> usize {
-        let digit = rem / bucket.get() + usize::try_from(bucket.extra()).unwrap_or(1);
+        let digit = rem / bucket.get() + usize::from(bucket.extra());
         let rem = rem % bucket.get();
         output.push_str(&digit.to_string());
         rem
@@ -0,0 +1,342 @@
+use serde::Serialize;
+use std::fmt;
+
+use crate::{
+    app_error::AppError,
+    input::{Action, InputRow, InputSource, Position, Rejected},
+};
+
+/// The final state of a single input row
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Applied,
+    Created,
+    Deleted,
+    Unchanged,
+    CallsignNotFound,
+    OriginNotFound,
+    MidpointNotFound,
+    DestinationNotFound,
+    InvalidCallsign,
+    AmbiguousAirline,
+    DatabaseError,
+    ParseError,
+    RolledBack,
+    NotAttempted,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let disp = match self {
+            Self::Applied => "applied",
+            Self::Created => "created",
+            Self::Deleted => "deleted",
+            Self::Unchanged => "unchanged",
+            Self::CallsignNotFound => "callsign not found",
+            Self::OriginNotFound => "origin not found",
+            Self::MidpointNotFound => "midpoint not found",
+            Self::DestinationNotFound => "destination not found",
+            Self::InvalidCallsign => "invalid callsign",
+            Self::AmbiguousAirline => "ambiguous airline",
+            Self::DatabaseError => "database error",
+            Self::ParseError => "parse error",
+            Self::RolledBack => "rolled back",
+            Self::NotAttempted => "not attempted",
+        };
+        write!(f, "{disp}")
+    }
+}
+
+impl Outcome {
+    /// The row was either written, or didn't need to be
+    pub const fn is_success(self) -> bool {
+        matches!(
+            self,
+            Self::Applied | Self::Created | Self::Deleted | Self::Unchanged
+        )
+    }
+
+    /// The row failed with an error, rather than simply not being found
+    pub const fn is_error(self) -> bool {
+        matches!(
+            self,
+            Self::InvalidCallsign | Self::AmbiguousAirline | Self::DatabaseError | Self::ParseError
+        )
+    }
+}
+
+impl From<&AppError> for Outcome {
+    fn from(e: &AppError) -> Self {
+        match e {
+            AppError::Callsign(_) => Self::InvalidCallsign,
+            AppError::AmbiguousAirline(_) => Self::AmbiguousAirline,
+            _ => Self::DatabaseError,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowReport {
+    pub position: Position,
+    pub callsign: String,
+    pub origin: String,
+    pub destination: String,
+    pub midpoint: Option<String>,
+    pub action: Action,
+    pub outcome: Outcome,
+    pub message: Option<String>,
+    pub raw: Option<String>,
+}
+
+impl RowReport {
+    pub fn new(row: &InputRow, outcome: Outcome, message: Option<String>) -> Self {
+        Self {
+            position: row.position,
+            callsign: row.flightroute.callsign.clone(),
+            origin: row.flightroute.origin.clone(),
+            destination: row.flightroute.destination.clone(),
+            midpoint: row.flightroute.midpoint.clone(),
+            action: row.flightroute.action(),
+            outcome,
+            message,
+            raw: None,
+        }
+    }
+}
+
+impl From<&Rejected> for RowReport {
+    fn from(rejected: &Rejected) -> Self {
+        Self {
+            position: rejected.position,
+            callsign: String::new(),
+            origin: String::new(),
+            destination: String::new(),
+            midpoint: None,
+            action: Action::default(),
+            outcome: Outcome::ParseError,
+            message: Some(rejected.error.clone()),
+            raw: Some(rejected.raw.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    rows: Vec<RowReport>,
+    keys_deleted: usize,
+}
+
+impl Report {
+    /// Rows aren't logged here, as applied rows are logged in their span, and parse errors as they are read
+    pub fn push(&mut self, row: RowReport) {
+        self.rows.push(row);
+    }
+
+    /// Record the result of applying a row, an error is converted into its outcome, with the error as the message
+    pub fn push_result(&mut self, row: &InputRow, result: Result<Outcome, AppError>) {
+        let report = match result {
+            Ok(outcome) => RowReport::new(row, outcome, None),
+            Err(e) => RowReport::new(row, Outcome::from(&e), Some(format!("{e:?}"))),
+        };
+        self.push(report);
+    }
+
+    /// Record every row left over once the file has been stopped, so that the report still covers the whole input
+    pub fn push_not_attempted(&mut self, rows: &[InputRow]) {
+        for row in rows {
+            self.push(RowReport::new(row, Outcome::NotAttempted, None));
+        }
+    }
+
+    /// Record the number of cache keys that existed, and were deleted
+    pub const fn add_keys_deleted(&mut self, count: usize) {
+        self.keys_deleted += count;
+    }
+
+    /// Rows which failed with an error
+    pub fn errors(&self) -> impl Iterator<Item = &RowReport> {
+        self.rows.iter().filter(|row| row.outcome.is_error())
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Every row was either applied, created, deleted, or unchanged
+    pub fn is_clean(&self) -> bool {
+        self.rows.iter().all(|row| row.outcome.is_success())
+    }
+
+    /// The transaction has been rolled back, so nothing that was applied has actually been written
+    pub fn roll_back(&mut self) {
+        for row in &mut self.rows {
+            if matches!(
+                row.outcome,
+                Outcome::Applied | Outcome::Created | Outcome::Deleted
+            ) {
+                row.outcome = Outcome::RolledBack;
+            }
+        }
+    }
+
+    pub fn is_rolled_back(&self) -> bool {
+        self.rows
+            .iter()
+            .any(|row| row.outcome == Outcome::RolledBack)
+    }
+
+    /// Rows that were written, as opposed to already matching the input
+    pub fn changed(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|row| {
+                matches!(
+                    row.outcome,
+                    Outcome::Applied | Outcome::Created | Outcome::Deleted
+                )
+            })
+            .count()
+    }
+
+    /// Rows that already matched the input, so nothing was written, or invalidated
+    pub fn unchanged(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|row| row.outcome == Outcome::Unchanged)
+            .count()
+    }
+
+    /// Count of changed and unchanged rows, then of rows for each outcome, in the order they were first seen
+    pub fn summary(&self) -> String {
+        let mut counts: Vec<(Outcome, usize)> = vec![];
+        for row in &self.rows {
+            match counts
+                .iter_mut()
+                .find(|(outcome, _)| *outcome == row.outcome)
+            {
+                Some((_, count)) => *count += 1,
+                None => counts.push((row.outcome, 1)),
+            }
+        }
+        let counts = counts
+            .iter()
+            .map(|(outcome, count)| format!("{outcome}: {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{} rows, {} changed, {} unchanged; {counts}; {} cache keys deleted",
+            self.rows.len(),
+            self.changed(),
+            self.unchanged(),
+            self.keys_deleted
+        )
+    }
+
+    /// Write the report as both `[input].report.csv` and `[input].report.json`, next to the input file
+    pub fn write(&self, input: &InputSource) -> Result<(), AppError> {
+        let mut rows = self.rows.iter().collect::<Vec<_>>();
+        rows.sort_by_key(|row| row.position);
+
+        let mut csv_writer = csv::Writer::from_path(input.report_path("report.csv"))?;
+        for row in &rows {
+            csv_writer.serialize(row)?;
+        }
+        csv_writer.flush()?;
+
+        let json_file = std::fs::File::create(input.report_path("report.json"))?;
+        serde_json::to_writer_pretty(json_file, &rows)?;
+        Ok(())
+    }
+}
+
+/// Run tests with
+///
+/// cargo watch -q -c -w src/ -x 'test report_ -- --nocapture'
+#[cfg(test)]
+#[allow(clippy::pedantic, clippy::nursery, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::input::UpdatedFlightroute;
+
+    fn row(line: u64, outcome: Outcome) -> RowReport {
+        let row = InputRow {
+            position: Position::Line(line),
+            flightroute: UpdatedFlightroute {
+                callsign: "SWA2238".to_owned(),
+                origin: "PDX".to_owned(),
+                destination: "PHX".to_owned(),
+                midpoint: None,
+                action: None,
+            },
+        };
+        RowReport::new(&row, outcome, None)
+    }
+
+    #[test]
+    fn report_push_not_attempted() {
+        // SETUP
+        let mut report = Report::default();
+        report.push(row(2, Outcome::Applied));
+        let rows = [3, 4].map(|line| InputRow {
+            position: Position::Line(line),
+            flightroute: UpdatedFlightroute {
+                callsign: "SWA2238".to_owned(),
+                origin: "PDX".to_owned(),
+                destination: "PHX".to_owned(),
+                midpoint: None,
+                action: None,
+            },
+        });
+
+        // ACTION
+        report.push_not_attempted(&rows);
+
+        // CHECK
+        assert!(!report.is_clean());
+        assert!(!report.has_errors());
+        assert_eq!(
+            report.summary(),
+            "3 rows, 1 changed, 0 unchanged; applied: 1, not attempted: 2; 0 cache keys deleted"
+        );
+    }
+
+    #[test]
+    fn report_summary() {
+        // SETUP
+        let mut report = Report::default();
+        report.push(row(2, Outcome::Applied));
+        report.push(row(3, Outcome::Unchanged));
+        report.push(row(4, Outcome::Unchanged));
+        report.push(row(5, Outcome::CallsignNotFound));
+        report.add_keys_deleted(2);
+        report.add_keys_deleted(1);
+
+        // ACTION
+        let result = report.summary();
+
+        // CHECK
+        assert_eq!(report.changed(), 1);
+        assert_eq!(report.unchanged(), 2);
+        assert_eq!(
+            result,
+            "4 rows, 1 changed, 2 unchanged; applied: 1, unchanged: 2, callsign not found: 1; 3 cache keys deleted"
+        );
+    }
+
+    #[test]
+    fn report_outcome_from_app_error() {
+        // SETUP
+        let ambiguous = AppError::AmbiguousAirline("BA matches 2 airlines".to_owned());
+        let callsign = AppError::Callsign("A".to_owned());
+
+        // ACTION
+        let ambiguous = Outcome::from(&ambiguous);
+        let callsign = Outcome::from(&callsign);
+
+        // CHECK
+        assert_eq!(ambiguous, Outcome::AmbiguousAirline);
+        assert!(ambiguous.is_error());
+        assert_eq!(callsign, Outcome::InvalidCallsign);
+    }
+}
@@ -0,0 +1,103 @@
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::{
+    app_error::AppError,
+    callsign::{Callsign, Validate},
+    db::{AuditEntry, CacheKey, ModelAudit, ModelFlightroute, Redis},
+    parse_env::AppEnv,
+    updater,
+};
+
+/// The cache keys for a callsign, as it currently is in the transaction
+async fn cache_keys(
+    transaction: &mut Transaction<'_, Postgres>,
+    callsign: &Callsign,
+) -> Result<Vec<CacheKey>, AppError> {
+    Ok(ModelFlightroute::get_transaction(transaction, callsign)
+        .await?
+        .map(|flightroute| CacheKey::flightroute(&flightroute))
+        .unwrap_or_default())
+}
+
+/// Revert every change made by a previous run, newest first, in a single transaction.
+/// A flightroute which has been changed again since that run is refused, unless `force` is set.
+/// Each revert is itself audited, under a new run id, so a rollback can also be rolled back.
+/// Returns false if any change was refused
+pub async fn rollback(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    redis: &mut Redis,
+    run_id: &str,
+    force: bool,
+) -> Result<bool, AppError> {
+    let entries = ModelAudit::get_run(postgres, run_id).await?;
+    if entries.is_empty() {
+        return Err(AppError::Internal(format!(
+            "no changes found for run_id {run_id}"
+        )));
+    }
+    let new_run_id = ModelAudit::new_run_id(postgres).await?;
+    let source = format!("rollback {run_id}");
+    tracing::info!("run_id: {new_run_id}");
+
+    let mut transaction = postgres.begin().await?;
+    let mut keys = vec![];
+    let mut restored = 0;
+    let mut refused = 0;
+
+    for entry in &entries {
+        let current = entry.current(&mut transaction).await?;
+        if current.unwrap_or_default() != entry.new_route_ids() {
+            if !force {
+                println!(
+                    "\x1b[31m{} flightroute_id {}: changed since run, refusing\x1b[0m",
+                    entry.callsign, entry.flightroute_id
+                );
+                refused += 1;
+                continue;
+            }
+            tracing::warn!(
+                "{} flightroute_id {}: changed since run, forcing",
+                entry.callsign,
+                entry.flightroute_id
+            );
+        }
+
+        let callsign = Callsign::validate(&entry.callsign)?;
+        keys.extend(cache_keys(&mut transaction, &callsign).await?);
+        entry.revert(&mut transaction, current).await?;
+        keys.extend(cache_keys(&mut transaction, &callsign).await?);
+
+        ModelAudit::insert(
+            &mut transaction,
+            &AuditEntry {
+                run_id: &new_run_id,
+                source: &source,
+                flightroute_id: entry.flightroute_id,
+                flightroute_callsign_id: entry.flightroute_callsign_id,
+                callsign: entry.callsign.clone(),
+                action: "rollback",
+                old: current.unwrap_or_default(),
+                new: entry.old_route_ids(),
+            },
+        )
+        .await?;
+        tracing::info!(
+            "{} flightroute_id {}: reverted {}",
+            entry.callsign,
+            entry.flightroute_id,
+            entry.action
+        );
+        restored += 1;
+    }
+
+    transaction.commit().await?;
+    let keys_deleted = updater::invalidate(app_env, postgres, redis, &keys).await?;
+
+    println!(
+        "rollback {run_id}: {} changes; restored: {restored}, refused: {refused}; {keys_deleted} cache keys deleted",
+        entries.len()
+    );
+    println!("run_id: {new_run_id}");
+    Ok(refused == 0)
+}
@@ -0,0 +1,770 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashSet;
+use tracing::{field, Instrument, Span};
+
+use crate::{
+    app_error::AppError,
+    callsign::{Callsign, Validate},
+    db::{
+        self, AirportCode, AuditEntry, CacheKey, Midpoint, ModelAirport, ModelAudit,
+        ModelFlightroute, Redis, RouteIds,
+    },
+    input::{Action, InputRow, UpdatedFlightroute, CLEAR_MIDPOINT},
+    parse_env::AppEnv,
+    report::{Outcome, Report},
+};
+
+/// The change that a row will make to a flightroute
+enum Change {
+    Update {
+        origin: ModelAirport,
+        midpoint: Midpoint<ModelAirport>,
+        destination: ModelAirport,
+    },
+    Delete,
+    ClearMidpoint,
+}
+
+/// A row which has been resolved against Postgres, and is ready to be applied.
+/// The flightroute is only `None` when it doesn't exist yet, and is going to be created
+struct Resolved {
+    callsign: Callsign,
+    flightroute: Option<ModelFlightroute>,
+    change: Change,
+}
+
+impl Resolved {
+    /// The resolved airport ids already match the current route, so there is nothing to write, or invalidate
+    fn is_unchanged(&self) -> bool {
+        let Some(flightroute) = self.flightroute.as_ref() else {
+            return false;
+        };
+        let current = flightroute.route_ids();
+        match &self.change {
+            Change::Update {
+                origin,
+                midpoint,
+                destination,
+            } => updated_route_ids(current, origin, midpoint, destination) == current,
+            Change::Delete => false,
+            Change::ClearMidpoint => current.midpoint.is_none(),
+        }
+    }
+}
+
+/// Resolve an airport from either an ICAO or IATA code, an invalid code is treated as not found
+async fn resolve_airport(
+    transaction: &mut Transaction<'_, Postgres>,
+    code: &str,
+) -> Result<Option<ModelAirport>, AppError> {
+    let Some(code) = AirportCode::parse(code) else {
+        return Ok(None);
+    };
+    let airport = ModelAirport::get(&mut *transaction, &code).await?;
+    if let Some(airport) = airport.as_ref() {
+        tracing::debug!(
+            "{code} matched airport_id {} by {} code",
+            airport.airport_id,
+            code.code_type()
+        );
+    }
+    Ok(airport)
+}
+
+/// Resolve a row into a flightroute and the change to make to it, or the outcome explaining why it can't be.
+/// With upsert, a callsign that isn't found is resolved without a flightroute, so that one can be created, this only applies to the update action.
+/// Resolved within the transaction, so that the changes of earlier rows in the same transaction are visible
+async fn resolve(
+    transaction: &mut Transaction<'_, Postgres>,
+    row: &UpdatedFlightroute,
+    upsert: bool,
+) -> Result<Result<Resolved, Outcome>, AppError> {
+    let callsign = Callsign::validate(&row.callsign)?;
+    let flightroute = ModelFlightroute::get_transaction(transaction, &callsign).await?;
+    if let Some(flightroute) = flightroute.as_ref() {
+        record_old(&Span::current(), flightroute);
+    }
+    let action = row.action();
+    if flightroute.is_none() && (action != Action::Update || !upsert) {
+        return Ok(Err(Outcome::CallsignNotFound));
+    }
+
+    let change = match action {
+        Action::Delete => Change::Delete,
+        Action::ClearMidpoint => Change::ClearMidpoint,
+        Action::Update => {
+            let Some(origin) = resolve_airport(transaction, &row.origin).await? else {
+                return Ok(Err(Outcome::OriginNotFound));
+            };
+            let Some(destination) = resolve_airport(transaction, &row.destination).await? else {
+                return Ok(Err(Outcome::DestinationNotFound));
+            };
+            let midpoint = match row.midpoint() {
+                Midpoint::Unchanged => Midpoint::Unchanged,
+                Midpoint::Clear => Midpoint::Clear,
+                Midpoint::Set(midpoint) => {
+                    let Some(midpoint) = resolve_airport(transaction, midpoint).await? else {
+                        return Ok(Err(Outcome::MidpointNotFound));
+                    };
+                    Midpoint::Set(midpoint)
+                }
+            };
+            Change::Update {
+                origin,
+                midpoint,
+                destination,
+            }
+        }
+    };
+
+    Ok(Ok(Resolved {
+        callsign,
+        flightroute,
+        change,
+    }))
+}
+
+/// The run, and input source, that a change belongs to, recorded alongside every change in the audit table
+pub struct Run<'a> {
+    pub id: &'a str,
+    pub source: &'a str,
+}
+
+/// The airport ids a flightroute will have after an update
+const fn updated_route_ids(
+    old: RouteIds,
+    origin: &ModelAirport,
+    midpoint: &Midpoint<ModelAirport>,
+    destination: &ModelAirport,
+) -> RouteIds {
+    RouteIds {
+        origin: Some(origin.airport_id),
+        midpoint: match midpoint {
+            Midpoint::Unchanged => old.midpoint,
+            Midpoint::Clear => None,
+            Midpoint::Set(midpoint) => Some(midpoint.airport_id),
+        },
+        destination: Some(destination.airport_id),
+    }
+}
+
+/// A span for a single row, the flightroute_id, and old airports, are recorded once the flightroute has been found
+fn row_span(row: &InputRow) -> Span {
+    tracing::info_span!(
+        "row",
+        position = %row.position,
+        callsign = %row.flightroute.callsign,
+        flightroute_id = field::Empty,
+        old_origin = field::Empty,
+        old_midpoint = field::Empty,
+        old_destination = field::Empty,
+        new_origin = %row.flightroute.origin,
+        new_midpoint = row.flightroute.midpoint.as_deref(),
+        new_destination = %row.flightroute.destination,
+    )
+}
+
+/// Record the current route of a flightroute, by ICAO code, in a row span
+fn record_old(span: &Span, flightroute: &ModelFlightroute) {
+    span.record("flightroute_id", flightroute.flightroute_id);
+    span.record("old_origin", flightroute.origin_airport_icao_code.as_str());
+    span.record(
+        "old_midpoint",
+        flightroute.midpoint_airport_icao_code.as_deref(),
+    );
+    span.record(
+        "old_destination",
+        flightroute.destination_airport_icao_code.as_str(),
+    );
+}
+
+/// Log the outcome of a row, in its span, a row which couldn't be applied is a warning, and an error is an error
+fn log_outcome(span: &Span, result: &Result<Outcome, AppError>) {
+    span.in_scope(|| match result {
+        Ok(outcome) if outcome.is_success() => tracing::info!(%outcome, "{outcome}"),
+        Ok(outcome) => tracing::warn!(%outcome, "{outcome}"),
+        Err(e) => tracing::error!(outcome = %Outcome::from(e), "{e:?}"),
+    });
+}
+
+/// Write a resolved row, and its audit entry, returning the outcome, and the cache keys that need to be deleted once the transaction has been committed
+async fn write(
+    transaction: &mut Transaction<'_, Postgres>,
+    run: &Run<'_>,
+    resolved: &Resolved,
+) -> Result<(Outcome, Vec<CacheKey>), AppError> {
+    if resolved.is_unchanged() {
+        return Ok((Outcome::Unchanged, vec![]));
+    }
+    let audit = |flightroute: &ModelFlightroute, action, new| AuditEntry {
+        run_id: run.id,
+        source: run.source,
+        flightroute_id: flightroute.flightroute_id,
+        flightroute_callsign_id: Some(flightroute.flightroute_callsign_id),
+        callsign: resolved.callsign.to_string(),
+        action,
+        old: flightroute.route_ids(),
+        new,
+    };
+
+    match (&resolved.flightroute, &resolved.change) {
+        (
+            Some(flightroute),
+            Change::Update {
+                origin,
+                midpoint,
+                destination,
+            },
+        ) => {
+            flightroute
+                .update(transaction, origin, midpoint, destination)
+                .await?;
+            let new = updated_route_ids(flightroute.route_ids(), origin, midpoint, destination);
+            ModelAudit::insert(transaction, &audit(flightroute, "update", new)).await?;
+            Ok((Outcome::Applied, CacheKey::flightroute(flightroute)))
+        }
+        (Some(flightroute), Change::ClearMidpoint) => {
+            flightroute.clear_midpoint(transaction).await?;
+            let new = RouteIds {
+                midpoint: None,
+                ..flightroute.route_ids()
+            };
+            ModelAudit::insert(transaction, &audit(flightroute, "clear_midpoint", new)).await?;
+            Ok((Outcome::Applied, CacheKey::flightroute(flightroute)))
+        }
+        (Some(flightroute), Change::Delete) => {
+            flightroute.delete(transaction).await?;
+            ModelAudit::insert(
+                transaction,
+                &audit(flightroute, "delete", RouteIds::default()),
+            )
+            .await?;
+            Ok((Outcome::Deleted, CacheKey::flightroute(flightroute)))
+        }
+        (
+            None,
+            Change::Update {
+                origin,
+                midpoint,
+                destination,
+            },
+        ) => {
+            let flightroute_id = ModelFlightroute::insert(
+                transaction,
+                &resolved.callsign,
+                origin,
+                midpoint,
+                destination,
+            )
+            .await?;
+            Span::current().record("flightroute_id", flightroute_id);
+            tracing::debug!(
+                "{} created flightroute_id {flightroute_id}",
+                resolved.callsign
+            );
+            let created =
+                ModelFlightroute::get_transaction(transaction, &resolved.callsign).await?;
+            ModelAudit::insert(
+                transaction,
+                &AuditEntry {
+                    run_id: run.id,
+                    source: run.source,
+                    flightroute_id,
+                    flightroute_callsign_id: created
+                        .as_ref()
+                        .map(|flightroute| flightroute.flightroute_callsign_id),
+                    callsign: resolved.callsign.to_string(),
+                    action: "create",
+                    old: RouteIds::default(),
+                    new: updated_route_ids(RouteIds::default(), origin, midpoint, destination),
+                },
+            )
+            .await?;
+            let keys = created
+                .map(|flightroute| CacheKey::flightroute(&flightroute))
+                .unwrap_or_default();
+            Ok((Outcome::Created, keys))
+        }
+        (None, Change::Delete | Change::ClearMidpoint) => Ok((Outcome::CallsignNotFound, vec![])),
+    }
+}
+
+/// Delete cache keys, of changes that have been committed, and then, with `WARM_CACHE`, re-populate them with the updated flightroutes.
+/// The changes can't be undone by then, so on a Redis error the keys are logged, to be deleted by hand
+pub async fn invalidate(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    redis: &mut Redis,
+    keys: &[CacheKey],
+) -> Result<usize, AppError> {
+    let invalidated = async {
+        let deleted = db::delete_keys(redis, keys, app_env.redis_batch_size).await?;
+        if app_env.warm_cache {
+            let warmed = db::warm_keys(postgres, redis, keys).await?;
+            tracing::debug!("warmed {warmed} cache keys");
+        }
+        Ok::<_, AppError>(deleted)
+    }
+    .await;
+    if let Err(e) = &invalidated {
+        let keys = keys
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        tracing::error!("{e:?}, cache keys may not have been invalidated: {keys}");
+    }
+    invalidated
+}
+
+/// Resolve, and then apply, a single row in its own transaction, returning the cache keys to delete now that the row has been committed
+async fn apply_row(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    run: &Run<'_>,
+    row: &UpdatedFlightroute,
+) -> Result<(Outcome, Vec<CacheKey>), AppError> {
+    let mut transaction = postgres.begin().await?;
+    match resolve(&mut transaction, row, app_env.upsert).await? {
+        Ok(resolved) if resolved.is_unchanged() => Ok((Outcome::Unchanged, vec![])),
+        Ok(resolved) => {
+            let (outcome, keys) = write(&mut transaction, run, &resolved).await?;
+            transaction.commit().await?;
+            Ok((outcome, keys))
+        }
+        Err(outcome) => Ok((outcome, vec![])),
+    }
+}
+
+/// Apply every row in a single transaction, which is only committed if every row is either applied, created, or unchanged.
+/// The cache keys are collected, and only deleted after a successful commit.
+/// A database error aborts the transaction, so the remaining rows would only fail with the same error, and aren't attempted
+async fn apply_atomic(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    redis: &mut Redis,
+    run: &Run<'_>,
+    rows: &[InputRow],
+    report: &mut Report,
+) -> Result<(), AppError> {
+    let mut transaction = postgres.begin().await?;
+    let mut keys = vec![];
+
+    for (index, row) in rows.iter().enumerate() {
+        let span = row_span(row);
+        let outcome = async {
+            match resolve(&mut transaction, &row.flightroute, app_env.upsert).await {
+                Ok(Ok(resolved)) => {
+                    write(&mut transaction, run, &resolved)
+                        .await
+                        .map(|(outcome, row_keys)| {
+                            keys.extend(row_keys);
+                            outcome
+                        })
+                }
+                Ok(Err(outcome)) => Ok(outcome),
+                Err(e) => Err(e),
+            }
+        }
+        .instrument(span.clone())
+        .await;
+        log_outcome(&span, &outcome);
+        let aborted = matches!(outcome, Err(AppError::SqlxError(_)));
+        report.push_result(row, outcome);
+        if aborted || (app_env.fail_fast && report.has_errors()) {
+            report.push_not_attempted(&rows[index + 1..]);
+            break;
+        }
+    }
+
+    if report.is_clean() {
+        transaction.commit().await?;
+        report.add_keys_deleted(invalidate(app_env, postgres, redis, &keys).await?);
+    } else {
+        transaction.rollback().await?;
+        report.roll_back();
+    }
+    Ok(())
+}
+
+/// Apply every row, either each in its own transaction, or all in a single transaction.
+/// The cache keys of committed rows are collected, and deleted in batches of `REDIS_BATCH_SIZE`, with any remainder deleted at the end.
+/// A Redis error stops the run, with every row still in the report, so that it can be written before the error is returned
+pub async fn apply(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    redis: &mut Redis,
+    run: &Run<'_>,
+    rows: &[InputRow],
+    report: &mut Report,
+) -> Result<(), AppError> {
+    if app_env.atomic {
+        return apply_atomic(app_env, postgres, redis, run, rows, report).await;
+    }
+    let mut keys = vec![];
+    for (index, row) in rows.iter().enumerate() {
+        let span = row_span(row);
+        let outcome = apply_row(app_env, postgres, run, &row.flightroute)
+            .instrument(span.clone())
+            .await
+            .map(|(outcome, row_keys)| {
+                keys.extend(row_keys);
+                outcome
+            });
+        log_outcome(&span, &outcome);
+        report.push_result(row, outcome);
+        if keys.len() >= app_env.redis_batch_size {
+            match invalidate(app_env, postgres, redis, &keys).await {
+                Ok(deleted) => report.add_keys_deleted(deleted),
+                Err(e) => {
+                    report.push_not_attempted(&rows[index + 1..]);
+                    return Err(e);
+                }
+            }
+            keys.clear();
+        }
+        if app_env.fail_fast && report.has_errors() {
+            report.push_not_attempted(&rows[index + 1..]);
+            break;
+        }
+    }
+    report.add_keys_deleted(invalidate(app_env, postgres, redis, &keys).await?);
+    Ok(())
+}
+
+/// An input row which would restore a flightroute to its current state, airports are given by their ICAO code
+fn restore_row(callsign: &Callsign, flightroute: &ModelFlightroute) -> UpdatedFlightroute {
+    let icao = |code: &str| AirportCode::Icao(code.to_owned()).to_input();
+    UpdatedFlightroute {
+        callsign: callsign.to_string(),
+        origin: icao(&flightroute.origin_airport_icao_code),
+        destination: icao(&flightroute.destination_airport_icao_code),
+        midpoint: Some(
+            flightroute
+                .midpoint_airport_icao_code
+                .as_deref()
+                .map_or_else(|| CLEAR_MIDPOINT.to_owned(), icao),
+        ),
+        action: None,
+    }
+}
+
+/// The current state of every flightroute that the rows will touch, as input rows which, when applied, would restore it.
+/// Only the first row for each callsign is used, as that is the state before any row is applied.
+/// With upsert, a flightroute that will be created is undone by deleting it
+async fn snapshot(
+    postgres: &PgPool,
+    rows: &[InputRow],
+    upsert: bool,
+) -> Result<Vec<UpdatedFlightroute>, AppError> {
+    let mut seen = HashSet::new();
+    let mut snapshot = vec![];
+    for InputRow {
+        flightroute: row, ..
+    } in rows
+    {
+        let Ok(callsign) = Callsign::validate(&row.callsign) else {
+            continue;
+        };
+        if !seen.insert(callsign.to_string()) {
+            continue;
+        }
+        match ModelFlightroute::get(postgres, &callsign).await? {
+            Some(flightroute) => snapshot.push(restore_row(&callsign, &flightroute)),
+            None if upsert && row.action() == Action::Update => {
+                snapshot.push(UpdatedFlightroute {
+                    callsign: callsign.to_string(),
+                    origin: String::new(),
+                    destination: String::new(),
+                    midpoint: None,
+                    action: Some(Action::Delete),
+                });
+            }
+            None => (),
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Input rows which would undo the rows, restoring a deleted flightroute requires `UPSERT`
+pub async fn undo(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    rows: &[InputRow],
+) -> Result<Vec<UpdatedFlightroute>, AppError> {
+    snapshot(postgres, rows, app_env.upsert).await
+}
+
+/// The current route of every callsign in the rows that has one
+pub async fn export(
+    postgres: &PgPool,
+    rows: &[InputRow],
+) -> Result<Vec<UpdatedFlightroute>, AppError> {
+    snapshot(postgres, rows, false).await
+}
+
+/// Print the current route of each callsign
+pub async fn show(postgres: &PgPool, callsigns: &[String]) -> Result<(), AppError> {
+    for input in callsigns {
+        let Ok(callsign) = Callsign::validate(input) else {
+            println!("{input}: {}", Outcome::InvalidCallsign);
+            continue;
+        };
+        let Some(flightroute) = ModelFlightroute::get(postgres, &callsign).await? else {
+            println!("{input}: {}", Outcome::CallsignNotFound);
+            continue;
+        };
+        println!("{input}: flightroute_id {}", flightroute.flightroute_id);
+        println!(
+            "    origin:      {} ({})",
+            flightroute.origin_airport_icao_code, flightroute.origin_airport_iata_code
+        );
+        match (
+            flightroute.midpoint_airport_icao_code.as_ref(),
+            flightroute.midpoint_airport_iata_code.as_ref(),
+        ) {
+            (Some(icao), Some(iata)) => println!("    midpoint:    {icao} ({iata})"),
+            (Some(icao), None) => println!("    midpoint:    {icao}"),
+            _ => println!("    midpoint:    {CLEAR_MIDPOINT}"),
+        }
+        println!(
+            "    destination: {} ({})",
+            flightroute.destination_airport_icao_code, flightroute.destination_airport_iata_code
+        );
+    }
+    Ok(())
+}
+
+/// Resolve every row against Postgres, and print the current route next to the proposed route, as well as the Redis keys that would be deleted.
+/// Nothing is written to either Postgres or Redis, the rows are resolved in a transaction which is never committed
+pub async fn dry_run(
+    app_env: &AppEnv,
+    postgres: &PgPool,
+    rows: &[InputRow],
+) -> Result<(), AppError> {
+    let mut transaction = postgres.begin().await?;
+    for InputRow { flightroute: i, .. } in rows {
+        let resolved = match resolve(&mut transaction, i, app_env.upsert).await {
+            Ok(Ok(resolved)) => resolved,
+            Ok(Err(outcome)) => {
+                println!("{}: {outcome}", i.callsign);
+                continue;
+            }
+            Err(e) => {
+                println!("{}: {}", i.callsign, Outcome::from(&e));
+                continue;
+            }
+        };
+
+        let Some(flightroute) = resolved.flightroute.as_ref() else {
+            if let Change::Update {
+                origin,
+                midpoint,
+                destination,
+            } = &resolved.change
+            {
+                println!("{}: new flightroute", i.callsign);
+                println!("    origin:      {}", origin.code);
+                if let Midpoint::Set(midpoint) = midpoint {
+                    println!("    midpoint:    {}", midpoint.code);
+                }
+                println!("    destination: {}", destination.code);
+            }
+            continue;
+        };
+
+        let midpoint = flightroute
+            .midpoint_airport_iata_code
+            .as_deref()
+            .unwrap_or(CLEAR_MIDPOINT);
+        println!(
+            "{}: {} flightroute_id {}",
+            i.callsign,
+            i.action(),
+            flightroute.flightroute_id
+        );
+        match &resolved.change {
+            Change::Update {
+                origin,
+                midpoint: proposed_midpoint,
+                destination,
+            } => {
+                let proposed_midpoint = match proposed_midpoint {
+                    Midpoint::Unchanged => midpoint.to_owned(),
+                    Midpoint::Clear => CLEAR_MIDPOINT.to_owned(),
+                    Midpoint::Set(proposed) => {
+                        format!("{} ({})", proposed.code, proposed.code.code_type())
+                    }
+                };
+                println!(
+                    "    origin:      {} -> {} ({})",
+                    flightroute.origin_airport_iata_code,
+                    origin.code,
+                    origin.code.code_type()
+                );
+                println!("    midpoint:    {midpoint} -> {proposed_midpoint}");
+                println!(
+                    "    destination: {} -> {} ({})",
+                    flightroute.destination_airport_iata_code,
+                    destination.code,
+                    destination.code.code_type()
+                );
+            }
+            Change::ClearMidpoint => {
+                println!("    midpoint:    {midpoint} -> {CLEAR_MIDPOINT}");
+            }
+            Change::Delete => {
+                println!(
+                    "    route:       {} -> {midpoint} -> {}",
+                    flightroute.origin_airport_iata_code, flightroute.destination_airport_iata_code
+                );
+            }
+        }
+        if resolved.is_unchanged() {
+            println!("    {}", Outcome::Unchanged);
+        } else {
+            println!(
+                "    redis del:   {}",
+                CacheKey::flightroute(flightroute)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Run tests with
+///
+/// cargo watch -q -c -w src/ -x 'test updater_ -- --nocapture'
+#[cfg(test)]
+#[allow(clippy::pedantic, clippy::nursery, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn airport(airport_id: i64) -> ModelAirport {
+        ModelAirport {
+            airport_id,
+            code: AirportCode::Icao(format!("K{airport_id:03}")),
+        }
+    }
+
+    fn resolved(midpoint: Option<i64>, change: Change) -> Resolved {
+        Resolved {
+            callsign: Callsign::Other("N123AB".to_owned()),
+            flightroute: Some(ModelFlightroute {
+                airport_origin_id: 1,
+                airport_midpoint_id: midpoint,
+                airport_destination_id: 3,
+                ..Default::default()
+            }),
+            change,
+        }
+    }
+
+    fn update(origin: i64, midpoint: Midpoint<ModelAirport>, destination: i64) -> Change {
+        Change::Update {
+            origin: airport(origin),
+            midpoint,
+            destination: airport(destination),
+        }
+    }
+
+    #[test]
+    fn updater_updated_route_ids() {
+        // FIXTURES
+        let old = RouteIds {
+            origin: Some(1),
+            midpoint: Some(2),
+            destination: Some(3),
+        };
+
+        // ACTION
+        let result01 = updated_route_ids(old, &airport(4), &Midpoint::Unchanged, &airport(5));
+        let result02 = updated_route_ids(old, &airport(4), &Midpoint::Clear, &airport(5));
+        let result03 = updated_route_ids(old, &airport(4), &Midpoint::Set(airport(6)), &airport(5));
+        let result04 = updated_route_ids(
+            RouteIds::default(),
+            &airport(4),
+            &Midpoint::Unchanged,
+            &airport(5),
+        );
+
+        // CHECK
+        assert_eq!(
+            result01,
+            RouteIds {
+                origin: Some(4),
+                midpoint: Some(2),
+                destination: Some(5),
+            }
+        );
+        assert_eq!(
+            result02,
+            RouteIds {
+                origin: Some(4),
+                midpoint: None,
+                destination: Some(5),
+            }
+        );
+        assert_eq!(
+            result03,
+            RouteIds {
+                origin: Some(4),
+                midpoint: Some(6),
+                destination: Some(5),
+            }
+        );
+        assert_eq!(
+            result04,
+            RouteIds {
+                origin: Some(4),
+                midpoint: None,
+                destination: Some(5),
+            }
+        );
+    }
+
+    #[test]
+    fn updater_resolved_is_unchanged_update() {
+        // ACTION
+        let result01 = resolved(Some(2), update(1, Midpoint::Unchanged, 3));
+        let result02 = resolved(Some(2), update(1, Midpoint::Set(airport(2)), 3));
+        let result03 = resolved(None, update(1, Midpoint::Clear, 3));
+        let result04 = resolved(Some(2), update(1, Midpoint::Clear, 3));
+        let result05 = resolved(Some(2), update(1, Midpoint::Set(airport(4)), 3));
+        let result06 = resolved(Some(2), update(4, Midpoint::Unchanged, 3));
+        let result07 = resolved(Some(2), update(1, Midpoint::Unchanged, 4));
+        let result08 = Resolved {
+            flightroute: None,
+            ..resolved(None, update(1, Midpoint::Unchanged, 3))
+        };
+
+        // CHECK
+        assert!(result01.is_unchanged());
+        assert!(result02.is_unchanged());
+        assert!(result03.is_unchanged());
+        assert!(!result04.is_unchanged());
+        assert!(!result05.is_unchanged());
+        assert!(!result06.is_unchanged());
+        assert!(!result07.is_unchanged());
+        assert!(!result08.is_unchanged());
+    }
+
+    #[test]
+    fn updater_resolved_is_unchanged_clear_midpoint_and_delete() {
+        // ACTION
+        let result01 = resolved(None, Change::ClearMidpoint);
+        let result02 = resolved(Some(2), Change::ClearMidpoint);
+        let result03 = resolved(None, Change::Delete);
+        let result04 = resolved(Some(2), Change::Delete);
+
+        // CHECK
+        assert!(result01.is_unchanged());
+        assert!(!result02.is_unchanged());
+        assert!(!result03.is_unchanged());
+        assert!(!result04.is_unchanged());
+    }
+}